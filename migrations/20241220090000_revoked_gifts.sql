@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS revoked_gifts (
+    jti uuid PRIMARY KEY,
+    expires_at timestamptz NOT NULL
+);
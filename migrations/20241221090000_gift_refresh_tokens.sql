@@ -0,0 +1,18 @@
+CREATE TABLE IF NOT EXISTS gift_refresh_families (
+    family uuid PRIMARY KEY,
+    payload text NOT NULL,
+    aud text,
+    sub text,
+    ttl bigint NOT NULL,
+    encrypted boolean NOT NULL,
+    revoked boolean NOT NULL DEFAULT false,
+    expires_at timestamptz NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS gift_refresh_tokens (
+    token_hash text PRIMARY KEY,
+    family uuid NOT NULL REFERENCES gift_refresh_families(family) ON DELETE CASCADE,
+    gift_jti uuid NOT NULL,
+    gift_expires_at timestamptz NOT NULL,
+    used boolean NOT NULL DEFAULT false
+);
@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS milk_buckets (
+    name text PRIMARY KEY,
+    tokens bigint NOT NULL,
+    refilled_at timestamptz NOT NULL
+);
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS milk_settings (
+    name text PRIMARY KEY,
+    capacity bigint NOT NULL,
+    refill bigint NOT NULL,
+    interval_ms bigint NOT NULL
+);
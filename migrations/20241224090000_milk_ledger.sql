@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS milk_ledger (
+    id bigserial PRIMARY KEY,
+    kind text NOT NULL,
+    client text NOT NULL,
+    tokens bigint,
+    unit text,
+    amount double precision,
+    recorded_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS milk_ledger_recorded_at ON milk_ledger(recorded_at);
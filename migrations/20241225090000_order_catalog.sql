@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS order_catalog (
+    item text PRIMARY KEY,
+    price_cents bigint NOT NULL
+);
@@ -0,0 +1 @@
+CREATE INDEX IF NOT EXISTS gift_refresh_tokens_gift_jti ON gift_refresh_tokens(gift_jti);
@@ -0,0 +1,8 @@
+UPDATE milk_ledger
+SET client = 'api_key:' || left(encode(sha256(convert_to(substr(client, 9), 'UTF8')), 'hex'), 16)
+WHERE client LIKE 'api_key:%';
+
+UPDATE milk_buckets
+SET name = substr(name, 1, strpos(name, ':api_key:') + 8)
+    || left(encode(sha256(convert_to(substr(name, strpos(name, ':api_key:') + 9), 'UTF8')), 'hex'), 16)
+WHERE strpos(name, ':api_key:') > 0;
@@ -1,66 +1,332 @@
-use actix_web::{http, post, web, HttpRequest, HttpResponse};
+mod convert;
+mod dependencies;
+mod orders;
+mod rules;
+mod validate;
+mod workspace;
+
+use actix_multipart::form::MultipartForm;
+use actix_web::{
+    guard::GuardContext,
+    http::{self, header::Header},
+    post, web, HttpRequest, HttpResponse,
+};
+
+pub use rules::Config;
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
-    cfg.service(post_manifest);
+    cfg.service(post_workspace)
+        .service(post_manifest)
+        .service(convert::post_convert)
+        .service(dependencies::post_dependencies);
 }
 
-#[derive(serde::Deserialize)]
+#[derive(Clone, serde::Deserialize)]
 struct Metadata {
     #[serde(default)]
     orders: Vec<Order>,
+    #[serde(flatten)]
+    sections: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Metadata {
+    // `workspace = true` marks metadata inherited from the workspace root;
+    // any other `workspace` value is an ordinary section.
+    fn is_inherited(&self) -> bool {
+        self.sections.get("workspace") == Some(&serde_json::Value::Bool(true))
+    }
 }
 
 #[serde_with::serde_as]
-#[derive(serde::Deserialize)]
-struct Order {
+#[derive(Clone, serde::Deserialize)]
+pub(crate) struct Order {
     item: String,
     #[serde_as(deserialize_as = "serde_with::DefaultOnError")]
     #[serde(default)]
     quantity: Option<usize>,
 }
 
+#[derive(serde::Deserialize)]
+struct Mode {
+    #[serde(default)]
+    validate: bool,
+    #[serde(default)]
+    aggregate: bool,
+    rules: Option<String>,
+}
+
 #[post("/5/manifest")]
-async fn post_manifest(req: HttpRequest, text: String) -> HttpResponse {
+async fn post_manifest(
+    req: HttpRequest,
+    mode: web::Query<Mode>,
+    config: web::Data<Config>,
+    pool: web::Data<sqlx::PgPool>,
+    text: String,
+) -> HttpResponse {
     let content_type = req.headers().get(http::header::CONTENT_TYPE);
 
-    let Some(package) = (match content_type.map(|c| c.to_str()) {
-        Some(Ok("application/toml")) => {
-            toml::from_str::<cargo_manifest::Manifest<Metadata>>(&text).ok()
-        }
-        Some(Ok("application/json")) => {
-            serde_json::from_str::<cargo_manifest::Manifest<Metadata>>(&text).ok()
-        }
-        Some(Ok("application/yaml")) => {
-            serde_yml::from_str::<cargo_manifest::Manifest<Metadata>>(&text).ok()
+    let Some(rules) = config.rules(mode.rules.as_deref()) else {
+        return HttpResponse::BadRequest().body("Unknown rules");
+    };
+
+    if mode.validate {
+        let manifest = match content_type.map(|c| c.to_str()) {
+            Some(Ok("application/toml")) => {
+                toml::from_str::<serde_json::Value>(&text).map_err(|e| e.to_string())
+            }
+            Some(Ok("application/json")) => {
+                serde_json::from_str::<serde_json::Value>(&text).map_err(|e| e.to_string())
+            }
+            Some(Ok("application/yaml")) => {
+                serde_yml::from_str::<serde_json::Value>(&text).map_err(|e| e.to_string())
+            }
+            _ => return HttpResponse::UnsupportedMediaType().finish(),
+        };
+
+        let report = match manifest {
+            Ok(manifest) => validate::validate(&manifest, rules),
+            Err(e) => validate::Report::unparseable(e),
+        };
+
+        return HttpResponse::Ok().json(report);
+    }
+
+    let manifest = match parse(content_type.and_then(|c| c.to_str().ok()), text.as_bytes()) {
+        Ok(manifest) => manifest,
+        Err(response) => return response,
+    };
+
+    let Some(members) = manifest.and_then(|m| workspace::members(vec![m])) else {
+        return HttpResponse::BadRequest().body("Invalid manifest");
+    };
+
+    orders_response(&req, &mode, rules, &pool, members).await
+}
+
+fn is_multipart(ctx: &GuardContext) -> bool {
+    ctx.header::<http::header::ContentType>()
+        .is_some_and(|c| c.0.type_() == mime::MULTIPART)
+}
+
+#[post("/5/manifest", guard = "is_multipart")]
+async fn post_workspace(
+    req: HttpRequest,
+    mode: web::Query<Mode>,
+    config: web::Data<Config>,
+    pool: web::Data<sqlx::PgPool>,
+    upload: MultipartForm<workspace::Upload>,
+) -> HttpResponse {
+    let Some(rules) = config.rules(mode.rules.as_deref()) else {
+        return HttpResponse::BadRequest().body("Unknown rules");
+    };
+
+    let mut manifests = vec![];
+
+    for part in &upload.manifest {
+        match parse(Some(workspace::content_type(part)), &part.data) {
+            Ok(Some(manifest)) => manifests.push(manifest),
+            Ok(None) => return HttpResponse::BadRequest().body("Invalid manifest"),
+            Err(response) => return response,
         }
-        _ => return HttpResponse::UnsupportedMediaType().finish(),
-    })
-    .and_then(|m| m.package) else {
+    }
+
+    let Some(members) = workspace::members(manifests) else {
         return HttpResponse::BadRequest().body("Invalid manifest");
     };
 
-    if !package
-        .keywords
-        .and_then(|k| k.as_local())
-        .map(|k| k.contains(&"Christmas 2024".to_string()))
-        .unwrap_or_default()
-    {
-        return HttpResponse::BadRequest().body("Magic keyword not provided");
+    orders_response(&req, &mode, rules, &pool, members).await
+}
+
+fn parse<T: serde::de::DeserializeOwned>(
+    content_type: Option<&str>,
+    body: &[u8],
+) -> Result<Option<T>, HttpResponse> {
+    let Ok(text) = std::str::from_utf8(body) else {
+        return Ok(None);
+    };
+
+    match content_type {
+        Some("application/toml") => Ok(toml::from_str(text).ok()),
+        Some("application/json") => Ok(serde_json::from_str(text).ok()),
+        Some("application/yaml") => Ok(serde_yml::from_str(text).ok()),
+        _ => Err(HttpResponse::UnsupportedMediaType().finish()),
     }
+}
+
+// Orders are plain text unless JSON ranks higher; None if neither is
+// acceptable.
+fn accepts_json(req: &HttpRequest) -> Option<bool> {
+    let Ok(accept) = http::header::Accept::parse(req) else {
+        return Some(false);
+    };
 
-    let Some(orders) = package.metadata.map(|m| {
-        m.orders
+    let ranked = accept.ranked();
+    if ranked.is_empty() {
+        return Some(false);
+    }
+
+    ranked.iter().find_map(|m| match m.essence_str() {
+        "*/*" | "text/*" | "text/plain" => Some(false),
+        "application/*" | "application/json" => Some(true),
+        _ => None,
+    })
+}
+
+async fn orders_response(
+    req: &HttpRequest,
+    mode: &Mode,
+    rules: &rules::Rules,
+    pool: &sqlx::PgPool,
+    members: Vec<workspace::Member>,
+) -> HttpResponse {
+    if let Err(e) = members.iter().try_for_each(|m| rules.check(m)) {
+        return HttpResponse::BadRequest().body(e);
+    }
+
+    let orders = members
+        .into_iter()
+        .filter_map(|m| m.orders)
+        .flatten()
+        .collect::<Vec<_>>();
+
+    let Some(json) = accepts_json(req) else {
+        return HttpResponse::NotAcceptable().finish();
+    };
+
+    if json {
+        let Some(orders) = orders::aggregate(orders) else {
+            return HttpResponse::BadRequest().body("Order quantities overflow");
+        };
+        if orders.is_empty() {
+            return HttpResponse::NoContent().finish();
+        }
+
+        return match orders::invoice(orders, pool).await {
+            Some(invoice) => HttpResponse::Ok().json(invoice),
+            None => HttpResponse::BadRequest().body("Invoice total overflows"),
+        };
+    }
+
+    let orders = if mode.aggregate {
+        match orders::aggregate(orders) {
+            Some(orders) => orders,
+            None => return HttpResponse::BadRequest().body("Order quantities overflow"),
+        }
+    } else {
+        orders
             .into_iter()
-            .filter(|o| o.quantity.is_some())
-            .map(|o| format!("{}: {}", o.item, o.quantity.unwrap()))
-            .collect::<Vec<_>>()
-    }) else {
-        return HttpResponse::NoContent().finish();
+            .filter_map(|o| Some((o.item, o.quantity?)))
+            .collect()
     };
 
     if orders.is_empty() {
         return HttpResponse::NoContent().finish();
     }
 
+    let orders = orders
+        .into_iter()
+        .map(|(item, quantity)| format!("{item}: {quantity}"))
+        .collect::<Vec<_>>();
+
     HttpResponse::Ok().body(orders.join("\n"))
 }
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    fn accepts(accept: Option<&str>) -> Option<bool> {
+        let req = match accept {
+            Some(accept) => TestRequest::default().insert_header((http::header::ACCEPT, accept)),
+            None => TestRequest::default(),
+        };
+
+        accepts_json(&req.to_http_request())
+    }
+
+    async fn manifest(toml: &str) -> (http::StatusCode, String) {
+        let config = Config::from_secrets(&shuttle_runtime::SecretStore::new(Default::default()));
+        let pool = sqlx::PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(pool))
+                .service(post_manifest),
+        )
+        .await;
+
+        let req = TestRequest::post()
+            .uri("/5/manifest")
+            .insert_header((http::header::CONTENT_TYPE, "application/toml"))
+            .set_payload(toml.to_string())
+            .to_request();
+        let res = actix_web::test::call_service(&app, req).await;
+        let status = res.status();
+        let body = actix_web::test::read_body(res).await;
+
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[actix_web::test]
+    async fn lone_manifest_cannot_inherit_keywords() {
+        let toml = "[package]\nname = \"gift\"\nkeywords.workspace = true";
+
+        assert_eq!(
+            manifest(toml).await,
+            (
+                http::StatusCode::BAD_REQUEST,
+                "Magic keyword not provided".to_string()
+            )
+        );
+    }
+
+    #[actix_web::test]
+    async fn metadata_may_have_a_workspace_section() {
+        let toml = r#"
+[package]
+name = "gift"
+keywords = ["Christmas 2024"]
+
+[package.metadata]
+workspace = "yes"
+
+[[package.metadata.orders]]
+item = "Toy car"
+quantity = 2
+"#;
+
+        assert_eq!(
+            manifest(toml).await,
+            (http::StatusCode::OK, "Toy car: 2".to_string())
+        );
+    }
+
+    #[test]
+    fn negotiates_json() {
+        assert_eq!(accepts(Some("application/json")), Some(true));
+        assert_eq!(accepts(Some("application/json; charset=utf-8")), Some(true));
+        assert_eq!(
+            accepts(Some("text/plain;q=0.5, application/json")),
+            Some(true)
+        );
+        assert_eq!(accepts(Some("text/html, application/*;q=0.8")), Some(true));
+    }
+
+    #[test]
+    fn defaults_to_text() {
+        assert_eq!(accepts(None), Some(false));
+        assert_eq!(accepts(Some("*/*")), Some(false));
+        assert_eq!(
+            accepts(Some("application/json;q=0.5, text/plain")),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_types() {
+        assert_eq!(accepts(Some("text/html")), None);
+        assert_eq!(accepts(Some("application/xml")), None);
+    }
+}
@@ -0,0 +1,301 @@
+use actix_web::{
+    http::{self, header::Header},
+    post, HttpRequest, HttpResponse,
+};
+use serde_json::Value;
+
+use super::validate::{MANIFEST_KEYS, PACKAGE_KEYS};
+
+#[derive(Clone, Copy)]
+enum Format {
+    Toml,
+    Json,
+    Yaml,
+}
+
+impl Format {
+    fn from_mime(mime: &str) -> Option<Format> {
+        match mime {
+            "application/toml" => Some(Format::Toml),
+            "application/json" => Some(Format::Json),
+            "application/yaml" => Some(Format::Yaml),
+            _ => None,
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Format::Toml => "application/toml",
+            Format::Json => "application/json",
+            Format::Yaml => "application/yaml",
+        }
+    }
+
+    fn parse(self, text: &str) -> Option<Value> {
+        match self {
+            Format::Toml => toml::from_str(text).ok(),
+            Format::Json => serde_json::from_str(text).ok(),
+            Format::Yaml => serde_yml::from_str(text).ok(),
+        }
+    }
+
+    fn render(self, manifest: Value) -> Option<String> {
+        match self {
+            Format::Toml => toml::to_string(&canonical(manifest, MANIFEST_KEYS)?).ok(),
+            Format::Json => serde_json::to_string_pretty(&manifest).ok(),
+            Format::Yaml => serde_yml::to_string(&manifest).ok(),
+        }
+    }
+}
+
+fn accepted(req: &HttpRequest, default: Format) -> Option<Format> {
+    let Ok(accept) = http::header::Accept::parse(req) else {
+        return Some(default);
+    };
+
+    let ranked = accept.ranked();
+    if ranked.is_empty() {
+        return Some(default);
+    }
+
+    ranked.iter().find_map(|m| match m.essence_str() {
+        "*/*" | "application/*" => Some(default),
+        mime => Format::from_mime(mime),
+    })
+}
+
+// TOML has no null, so null entries are dropped; keys listed in `order`
+// come first in that order, the remaining keys follow alphabetically.
+fn canonical(value: Value, order: &[&str]) -> Option<toml::Value> {
+    let value = match value {
+        Value::Null => return None,
+        Value::Bool(b) => toml::Value::Boolean(b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => toml::Value::Integer(i),
+            None => toml::Value::Float(n.as_f64()?),
+        },
+        Value::String(s) => toml::Value::String(s),
+        Value::Array(values) => toml::Value::Array(
+            values
+                .into_iter()
+                .map(|v| canonical(v, &[]))
+                .collect::<Option<_>>()?,
+        ),
+        Value::Object(mut map) => {
+            let mut table = toml::Table::new();
+
+            let keys = order
+                .iter()
+                .map(|k| k.to_string())
+                .filter(|k| map.contains_key(k))
+                .collect::<Vec<_>>();
+
+            for key in keys {
+                let value = map.remove(&key).unwrap();
+                let nested = if key == "package" { PACKAGE_KEYS } else { &[] };
+
+                if !value.is_null() {
+                    table.insert(key, canonical(value, nested)?);
+                }
+            }
+
+            for (key, value) in map.into_iter().filter(|(_, v)| !v.is_null()) {
+                table.insert(key, canonical(value, &[])?);
+            }
+
+            toml::Value::Table(table)
+        }
+    };
+
+    Some(value)
+}
+
+#[post("/5/convert")]
+pub async fn post_convert(req: HttpRequest, text: String) -> HttpResponse {
+    let Some(format) = req
+        .headers()
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|c| c.to_str().ok())
+        .and_then(Format::from_mime)
+    else {
+        return HttpResponse::UnsupportedMediaType().finish();
+    };
+
+    let Some(target) = accepted(&req, format) else {
+        return HttpResponse::NotAcceptable().finish();
+    };
+
+    let Some(manifest) = format
+        .parse(&text)
+        .filter(|m| serde_json::from_value::<cargo_manifest::Manifest<Value>>(m.clone()).is_ok())
+    else {
+        return HttpResponse::BadRequest().body("Invalid manifest");
+    };
+
+    let Some(converted) = target.render(manifest) else {
+        return HttpResponse::BadRequest().body("Manifest cannot be represented in this format");
+    };
+
+    HttpResponse::Ok()
+        .content_type(target.content_type())
+        .body(converted)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::StatusCode, test, App};
+
+    use super::*;
+
+    const MANIFEST: &str = r#"
+[dependencies]
+serde = "1"
+
+[package]
+version = "0.1.0"
+name = "gift"
+edition = "2021"
+
+[package.metadata]
+wrapping = "red"
+
+[[package.metadata.orders]]
+item = "Toy car"
+quantity = 2
+"#;
+
+    struct Converted {
+        status: StatusCode,
+        content_type: Option<String>,
+        body: String,
+    }
+
+    async fn convert(content_type: &str, accept: Option<&str>, body: &str) -> Converted {
+        let app = test::init_service(App::new().service(post_convert)).await;
+
+        let mut req = test::TestRequest::post()
+            .uri("/5/convert")
+            .insert_header((http::header::CONTENT_TYPE, content_type))
+            .set_payload(body.to_string());
+        if let Some(accept) = accept {
+            req = req.insert_header((http::header::ACCEPT, accept));
+        }
+
+        let res = test::call_service(&app, req.to_request()).await;
+        let status = res.status();
+        let content_type = res
+            .headers()
+            .get(http::header::CONTENT_TYPE)
+            .map(|c| c.to_str().unwrap().to_string());
+        let body = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+
+        Converted {
+            status,
+            content_type,
+            body,
+        }
+    }
+
+    fn metadata(manifest: &Value) -> &Value {
+        &manifest["package"]["metadata"]
+    }
+
+    #[actix_web::test]
+    async fn orders_toml_canonically() {
+        let toml = convert("application/toml", None, MANIFEST).await.body;
+
+        let position = |needle: &str| toml.find(needle).unwrap();
+        assert!(position("[package]") < position("[dependencies]"));
+        assert!(position("name = ") < position("version = "));
+        assert!(position("version = ") < position("edition = "));
+    }
+
+    #[actix_web::test]
+    async fn orders_remaining_keys_alphabetically() {
+        let json = r#"{
+            "package": { "name": "gift", "zebra": 1, "apple": 2 },
+            "sleigh": {},
+            "dependencies": {}
+        }"#;
+        let toml = convert("application/json", Some("application/toml"), json)
+            .await
+            .body;
+
+        let position = |needle: &str| toml.find(needle).unwrap();
+        assert!(position("name = ") < position("apple = "));
+        assert!(position("apple = ") < position("zebra = "));
+        assert!(position("[dependencies]") < position("[sleigh]"));
+    }
+
+    #[actix_web::test]
+    async fn drops_nulls_for_toml() {
+        let json = r#"{ "package": { "name": "gift", "description": null } }"#;
+        let converted = convert("application/json", Some("application/toml"), json).await;
+
+        assert_eq!(converted.status, StatusCode::OK);
+        assert!(!converted.body.contains("description"));
+
+        let json = r#"{ "package": { "name": "gift", "keywords": [null] } }"#;
+        let converted = convert("application/json", Some("application/toml"), json).await;
+
+        assert_eq!(converted.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn preserves_metadata_across_formats() {
+        let original: Value = toml::from_str(MANIFEST).unwrap();
+
+        let json = convert("application/toml", Some("application/json"), MANIFEST).await;
+        let yaml = convert("application/json", Some("application/yaml"), &json.body).await;
+        let toml = convert("application/yaml", Some("application/toml"), &yaml.body).await;
+
+        let json: Value = serde_json::from_str(&json.body).unwrap();
+        let yaml: Value = serde_yml::from_str(&yaml.body).unwrap();
+        let toml: Value = toml::from_str(&toml.body).unwrap();
+
+        assert_eq!(metadata(&json), metadata(&original));
+        assert_eq!(metadata(&yaml), metadata(&original));
+        assert_eq!(metadata(&toml), metadata(&original));
+        assert_eq!(toml, original);
+    }
+
+    #[actix_web::test]
+    async fn negotiates_target_format() {
+        for (accept, content_type) in [
+            (None, "application/toml"),
+            (Some("*/*"), "application/toml"),
+            (Some("application/*"), "application/toml"),
+            (Some("application/json"), "application/json"),
+            (
+                Some("application/json;q=0.5, application/yaml"),
+                "application/yaml",
+            ),
+            (
+                Some("text/html, application/json;q=0.1"),
+                "application/json",
+            ),
+        ] {
+            let converted = convert("application/toml", accept, MANIFEST).await;
+
+            assert_eq!(converted.status, StatusCode::OK, "{accept:?}");
+            assert_eq!(
+                converted.content_type.as_deref(),
+                Some(content_type),
+                "{accept:?}"
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn rejects_unsupported_formats() {
+        let converted = convert("application/toml", Some("text/html"), MANIFEST).await;
+        assert_eq!(converted.status, StatusCode::NOT_ACCEPTABLE);
+
+        let converted = convert("text/plain", None, MANIFEST).await;
+        assert_eq!(converted.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let converted = convert("application/json", None, MANIFEST).await;
+        assert_eq!(converted.status, StatusCode::BAD_REQUEST);
+        assert_eq!(converted.body, "Invalid manifest");
+    }
+}
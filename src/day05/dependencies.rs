@@ -0,0 +1,392 @@
+use actix_web::{http, post, HttpRequest, HttpResponse};
+use cargo_manifest::{Dependency, DepsSet};
+
+#[derive(Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Kind {
+    Normal,
+    Dev,
+    Build,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Source {
+    Registry,
+    Git,
+    Path,
+    Workspace,
+}
+
+#[derive(serde::Serialize)]
+struct Entry {
+    name: String,
+    package: String,
+    kind: Kind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+    req: String,
+    source: Source,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    registry: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    git: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    branch: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rev: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+    optional: bool,
+    default_features: bool,
+    features: Vec<String>,
+}
+
+impl Entry {
+    fn new(name: String, dependency: Dependency, kind: Kind, target: Option<String>) -> Self {
+        let mut entry = Entry {
+            package: dependency.package().unwrap_or(&name).to_string(),
+            req: dependency.req().to_string(),
+            optional: dependency.optional(),
+            features: dependency.req_features().to_vec(),
+            name,
+            kind,
+            target,
+            source: Source::Registry,
+            registry: None,
+            git: None,
+            branch: None,
+            tag: None,
+            rev: None,
+            path: None,
+            default_features: true,
+        };
+
+        match dependency {
+            Dependency::Simple(_) => {}
+            Dependency::Inherited(_) => entry.source = Source::Workspace,
+            Dependency::Detailed(detail) => {
+                entry.source = match (&detail.git, &detail.path) {
+                    (Some(_), _) => Source::Git,
+                    (None, Some(_)) => Source::Path,
+                    (None, None) => Source::Registry,
+                };
+                entry.registry = detail.registry;
+                entry.git = detail.git;
+                entry.branch = detail.branch;
+                entry.tag = detail.tag;
+                entry.rev = detail.rev;
+                entry.path = detail.path;
+                entry.default_features = detail.default_features.unwrap_or(true);
+            }
+        }
+
+        entry
+    }
+
+    fn section(&self) -> String {
+        let section = match self.kind {
+            Kind::Normal => "dependencies",
+            Kind::Dev => "dev-dependencies",
+            Kind::Build => "build-dependencies",
+        };
+
+        match &self.target {
+            Some(target) => format!("target.{target}.{section}"),
+            None => section.to_string(),
+        }
+    }
+
+    fn is_wildcard(&self) -> bool {
+        if self.source != Source::Registry {
+            return false;
+        }
+
+        semver::VersionReq::parse(&self.req).is_ok_and(|r| {
+            r.comparators.is_empty() || r.comparators.iter().any(|c| c.op == semver::Op::Wildcard)
+        })
+    }
+}
+
+#[derive(serde::Serialize)]
+struct Warning {
+    dependency: String,
+    section: String,
+    message: String,
+}
+
+#[derive(serde::Serialize)]
+struct Analysis {
+    dependencies: Vec<Entry>,
+    warnings: Vec<Warning>,
+}
+
+fn entries(deps: Option<DepsSet>, kind: Kind, target: Option<&String>) -> Vec<Entry> {
+    deps.unwrap_or_default()
+        .into_iter()
+        .map(|(name, dependency)| Entry::new(name, dependency, kind, target.cloned()))
+        .collect()
+}
+
+fn analyse(manifest: cargo_manifest::Manifest<serde_json::Value>) -> Analysis {
+    let mut dependencies = vec![];
+
+    dependencies.extend(entries(manifest.dependencies, Kind::Normal, None));
+    dependencies.extend(entries(manifest.dev_dependencies, Kind::Dev, None));
+    dependencies.extend(entries(manifest.build_dependencies, Kind::Build, None));
+
+    for (target, deps) in manifest.target.unwrap_or_default() {
+        let target = Some(&target);
+        dependencies.extend(entries(Some(deps.dependencies), Kind::Normal, target));
+        dependencies.extend(entries(Some(deps.dev_dependencies), Kind::Dev, target));
+        dependencies.extend(entries(Some(deps.build_dependencies), Kind::Build, target));
+    }
+
+    let mut warnings = vec![];
+
+    for (i, entry) in dependencies.iter().enumerate() {
+        let mut warn = |message: String| {
+            warnings.push(Warning {
+                dependency: entry.name.clone(),
+                section: entry.section(),
+                message,
+            })
+        };
+
+        if let Some(other) = dependencies[..i]
+            .iter()
+            .find(|o| o.package == entry.package)
+        {
+            if other.kind == entry.kind && other.target == entry.target {
+                warn(format!("{} is declared more than once", entry.package));
+            } else if other.req != entry.req || other.source != entry.source {
+                warn(format!(
+                    "{} is also declared in {} with a different specification",
+                    entry.package,
+                    other.section()
+                ));
+            }
+        }
+
+        if entry.is_wildcard() {
+            warn(format!("wildcard version requirement {}", entry.req));
+        }
+
+        if entry.source == Source::Git && entry.rev.is_none() {
+            warn("git dependency is not pinned to a rev".to_string());
+        }
+    }
+
+    Analysis {
+        dependencies,
+        warnings,
+    }
+}
+
+#[post("/5/dependencies")]
+pub async fn post_dependencies(req: HttpRequest, text: String) -> HttpResponse {
+    let content_type = req
+        .headers()
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|c| c.to_str().ok());
+
+    let manifest = match super::parse(content_type, text.as_bytes()) {
+        Ok(Some(manifest)) => manifest,
+        Ok(None) => return HttpResponse::BadRequest().body("Invalid manifest"),
+        Err(response) => return response,
+    };
+
+    HttpResponse::Ok().json(analyse(manifest))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+
+    fn analysis(manifest: &str) -> Value {
+        let manifest = format!("[package]\nname = \"gift\"\n{manifest}");
+
+        serde_json::to_value(analyse(toml::from_str(&manifest).unwrap())).unwrap()
+    }
+
+    fn warnings(manifest: &str) -> Vec<(String, String, String)> {
+        analysis(manifest)["warnings"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|w| {
+                let field = |key: &str| w[key].as_str().unwrap().to_string();
+                (field("dependency"), field("section"), field("message"))
+            })
+            .collect()
+    }
+
+    fn warning(dependency: &str, section: &str, message: &str) -> (String, String, String) {
+        (
+            dependency.to_string(),
+            section.to_string(),
+            message.to_string(),
+        )
+    }
+
+    #[test]
+    fn sections_dependencies() {
+        let analysis = analysis(
+            r#"
+[dependencies]
+sleigh = "1"
+
+[dev-dependencies]
+bells = "1"
+
+[build-dependencies]
+snow = "1"
+
+[target.'cfg(unix)'.dependencies]
+chimney = "1"
+
+[target.'cfg(windows)'.dev-dependencies]
+window = "1"
+"#,
+        );
+
+        let sections = analysis["dependencies"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|d| {
+                (
+                    d["name"].as_str().unwrap(),
+                    d["kind"].as_str().unwrap(),
+                    d["target"].clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            sections,
+            [
+                ("sleigh", "normal", Value::Null),
+                ("bells", "dev", Value::Null),
+                ("snow", "build", Value::Null),
+                ("chimney", "normal", Value::from("cfg(unix)")),
+                ("window", "dev", Value::from("cfg(windows)")),
+            ]
+        );
+        assert!(analysis["warnings"].as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    fn classifies_sources() {
+        let analysis = analysis(
+            r#"
+[dependencies]
+sleigh = "1"
+bells = { version = "1", registry = "north-pole" }
+snow = { git = "https://example.com/snow", rev = "abc" }
+elf = { path = "../elf" }
+tree.workspace = true
+"#,
+        );
+
+        let sources = analysis["dependencies"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|d| (d["name"].as_str().unwrap(), d["source"].as_str().unwrap()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            sources,
+            [
+                ("bells", "registry"),
+                ("elf", "path"),
+                ("sleigh", "registry"),
+                ("snow", "git"),
+                ("tree", "workspace"),
+            ]
+        );
+    }
+
+    #[test]
+    fn warns_about_duplicates() {
+        let manifest = r#"
+[dependencies]
+sleigh = "1"
+fast-sleigh = { package = "sleigh", version = "1" }
+"#;
+
+        assert_eq!(
+            warnings(manifest),
+            [warning(
+                "sleigh",
+                "dependencies",
+                "sleigh is declared more than once"
+            )]
+        );
+    }
+
+    #[test]
+    fn warns_about_differing_specifications() {
+        let manifest = r#"
+[dependencies]
+sleigh = "1"
+
+[dev-dependencies]
+sleigh = "2"
+
+[target.'cfg(unix)'.dependencies]
+sleigh = "1"
+"#;
+
+        assert_eq!(
+            warnings(manifest),
+            [warning(
+                "sleigh",
+                "dev-dependencies",
+                "sleigh is also declared in dependencies with a different specification"
+            )]
+        );
+    }
+
+    #[test]
+    fn warns_about_wildcards() {
+        let manifest = r#"
+[dependencies]
+sleigh = "*"
+bells = "1.*"
+snow = "1"
+elf = { git = "https://example.com/elf", rev = "abc", version = "*" }
+"#;
+
+        assert_eq!(
+            warnings(manifest),
+            [
+                warning("bells", "dependencies", "wildcard version requirement 1.*"),
+                warning("sleigh", "dependencies", "wildcard version requirement *"),
+            ]
+        );
+    }
+
+    #[test]
+    fn warns_about_unpinned_git() {
+        let manifest = r#"
+[build-dependencies]
+snow = { git = "https://example.com/snow", branch = "main" }
+elf = { git = "https://example.com/elf", rev = "abc" }
+"#;
+
+        assert_eq!(
+            warnings(manifest),
+            [warning(
+                "snow",
+                "build-dependencies",
+                "git dependency is not pinned to a rev"
+            )]
+        );
+    }
+}
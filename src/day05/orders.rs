@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+
+use super::Order;
+
+pub fn aggregate(orders: Vec<Order>) -> Option<Vec<(String, usize)>> {
+    let mut aggregated: Vec<(String, usize)> = vec![];
+
+    for order in orders {
+        let Some(quantity) = order.quantity else {
+            continue;
+        };
+
+        match aggregated.iter_mut().find(|(item, _)| *item == order.item) {
+            Some((_, total)) => *total = total.checked_add(quantity)?,
+            None => aggregated.push((order.item, quantity)),
+        }
+    }
+
+    Some(aggregated)
+}
+
+#[derive(serde::Serialize)]
+struct LineItem {
+    item: String,
+    quantity: usize,
+    unit_price_cents: Option<i64>,
+    subtotal_cents: Option<i64>,
+}
+
+#[derive(serde::Serialize)]
+pub struct Invoice {
+    items: Vec<LineItem>,
+    total_cents: i64,
+    unpriced: Vec<String>,
+}
+
+pub async fn invoice(orders: Vec<(String, usize)>, pool: &sqlx::PgPool) -> Option<Invoice> {
+    let names = orders.iter().map(|(i, _)| i.clone()).collect::<Vec<_>>();
+
+    let prices = sqlx::query!(
+        "SELECT item, price_cents FROM order_catalog WHERE item = ANY($1)",
+        &names
+    )
+    .fetch_all(pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|p| (p.item, p.price_cents))
+    .collect::<HashMap<_, _>>();
+
+    bill(orders, &prices)
+}
+
+// None if any subtotal or the total does not fit in an i64.
+fn bill(orders: Vec<(String, usize)>, prices: &HashMap<String, i64>) -> Option<Invoice> {
+    let items = orders
+        .into_iter()
+        .map(|(item, quantity)| {
+            let unit_price_cents = prices.get(&item).copied();
+            let subtotal_cents = match unit_price_cents {
+                Some(p) => Some(p.checked_mul(i64::try_from(quantity).ok()?)?),
+                None => None,
+            };
+
+            Some(LineItem {
+                subtotal_cents,
+                item,
+                quantity,
+                unit_price_cents,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(Invoice {
+        total_cents: items
+            .iter()
+            .filter_map(|i| i.subtotal_cents)
+            .try_fold(0_i64, i64::checked_add)?,
+        unpriced: items
+            .iter()
+            .filter(|i| i.unit_price_cents.is_none())
+            .map(|i| i.item.clone())
+            .collect(),
+        items,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(item: &str, quantity: usize) -> Order {
+        Order {
+            item: item.to_string(),
+            quantity: Some(quantity),
+        }
+    }
+
+    #[test]
+    fn aggregates_by_item() {
+        let orders = vec![
+            order("Toy car", 2),
+            order("Lego brick", 1),
+            order("Toy car", 3),
+            Order {
+                item: "Snow".to_string(),
+                quantity: None,
+            },
+        ];
+
+        assert_eq!(
+            aggregate(orders),
+            Some(vec![
+                ("Toy car".to_string(), 5),
+                ("Lego brick".to_string(), 1)
+            ])
+        );
+    }
+
+    #[test]
+    fn aggregate_overflow_is_none() {
+        let orders = vec![order("Toy car", usize::MAX), order("Toy car", 1)];
+
+        assert_eq!(aggregate(orders), None);
+    }
+
+    #[test]
+    fn bills_priced_items() {
+        let prices = HashMap::from([("Toy car".to_string(), 250)]);
+        let invoice = bill(
+            vec![("Toy car".to_string(), 4), ("Snow".to_string(), 1)],
+            &prices,
+        )
+        .unwrap();
+
+        assert_eq!(invoice.total_cents, 1000);
+        assert_eq!(invoice.items[0].subtotal_cents, Some(1000));
+        assert_eq!(invoice.unpriced, vec!["Snow".to_string()]);
+    }
+
+    #[test]
+    fn bill_overflow_is_none() {
+        let prices = HashMap::from([
+            ("Toy car".to_string(), i64::MAX / 2 + 1),
+            ("Lego brick".to_string(), 2),
+        ]);
+
+        assert!(bill(vec![("Lego brick".to_string(), usize::MAX)], &prices).is_none());
+        assert!(bill(vec![("Toy car".to_string(), 2)], &prices).is_none());
+        assert!(bill(
+            vec![("Toy car".to_string(), 1), ("Toy car".to_string(), 1)],
+            &prices
+        )
+        .is_none());
+        assert!(bill(vec![("Toy car".to_string(), 1)], &prices).is_some());
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+
+use super::workspace::Member;
+
+const KEYWORD: &str = "Christmas 2024";
+
+#[derive(serde::Deserialize)]
+#[serde(default)]
+pub struct Rules {
+    pub required_keywords: Vec<String>,
+    pub forbidden_keywords: Vec<String>,
+    pub required_categories: Vec<String>,
+    pub allowed_categories: Option<Vec<String>>,
+    metadata_schema: Option<serde_json::Value>,
+    #[serde(skip)]
+    validator: Option<jsonschema::Validator>,
+}
+
+// Profiles still require the magic keyword unless they set
+// `required_keywords` themselves, an empty list included.
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            required_keywords: vec![KEYWORD.to_string()],
+            forbidden_keywords: vec![],
+            required_categories: vec![],
+            allowed_categories: None,
+            metadata_schema: None,
+            validator: None,
+        }
+    }
+}
+
+impl Rules {
+    fn compile(mut self) -> Self {
+        self.validator = self
+            .metadata_schema
+            .as_ref()
+            .map(|s| jsonschema::validator_for(s).unwrap());
+        self
+    }
+
+    pub fn check(&self, member: &Member) -> Result<(), String> {
+        if !self
+            .required_keywords
+            .iter()
+            .all(|k| member.keywords.contains(k))
+        {
+            return Err("Magic keyword not provided".to_string());
+        }
+
+        if let Some(k) = self
+            .forbidden_keywords
+            .iter()
+            .find(|k| member.keywords.contains(k))
+        {
+            return Err(format!("Forbidden keyword provided: {k}"));
+        }
+
+        if let Some(c) = self
+            .required_categories
+            .iter()
+            .find(|c| !member.categories.contains(c))
+        {
+            return Err(format!("Required category not provided: {c}"));
+        }
+
+        if let Some(allowed) = &self.allowed_categories {
+            if let Some(c) = member.categories.iter().find(|c| !allowed.contains(c)) {
+                return Err(format!("Category not allowed: {c}"));
+            }
+        }
+
+        let errors = self
+            .metadata_errors(&serde_json::Value::Object(member.sections.clone()))
+            .into_iter()
+            .map(|(path, e)| format!("{path}: {e}"))
+            .collect::<Vec<_>>();
+
+        if !errors.is_empty() {
+            return Err(format!("Invalid metadata: {}", errors.join("; ")));
+        }
+
+        Ok(())
+    }
+
+    pub fn metadata_errors(&self, sections: &serde_json::Value) -> Vec<(String, String)> {
+        let Some(validator) = &self.validator else {
+            return vec![];
+        };
+
+        validator
+            .iter_errors(sections)
+            .map(|e| (e.instance_path.to_string(), e.to_string()))
+            .collect()
+    }
+}
+
+pub struct Config {
+    default: Rules,
+    profiles: HashMap<String, Rules>,
+}
+
+impl Config {
+    pub fn from_secrets(secrets: &shuttle_runtime::SecretStore) -> Self {
+        let list = |key: &str| -> Option<Vec<String>> {
+            secrets.get(key).map(|l| {
+                l.split(',')
+                    .map(|i| i.trim().to_string())
+                    .filter(|i| !i.is_empty())
+                    .collect()
+            })
+        };
+
+        let default = Rules {
+            required_keywords: list("DAY05_REQUIRED_KEYWORDS")
+                .unwrap_or_else(|| vec![KEYWORD.to_string()]),
+            forbidden_keywords: list("DAY05_FORBIDDEN_KEYWORDS").unwrap_or_default(),
+            required_categories: list("DAY05_REQUIRED_CATEGORIES").unwrap_or_default(),
+            allowed_categories: list("DAY05_ALLOWED_CATEGORIES"),
+            metadata_schema: secrets
+                .get("DAY05_METADATA_SCHEMA")
+                .map(|s| serde_json::from_str(&s).unwrap()),
+            validator: None,
+        }
+        .compile();
+
+        let profiles = secrets
+            .get("DAY05_RULES")
+            .map(|r| serde_json::from_str::<HashMap<String, Rules>>(&r).unwrap())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, rules)| (name, rules.compile()))
+            .collect();
+
+        Self { default, profiles }
+    }
+
+    pub fn rules(&self, profile: Option<&str>) -> Option<&Rules> {
+        match profile {
+            Some(profile) => self.profiles.get(profile),
+            None => Some(&self.default),
+        }
+    }
+}
@@ -0,0 +1,701 @@
+use serde_json::Value;
+
+use super::{rules::Rules, Metadata};
+
+pub const MANIFEST_KEYS: &[&str] = &[
+    "cargo-features",
+    "package",
+    "project",
+    "workspace",
+    "lib",
+    "bin",
+    "example",
+    "test",
+    "bench",
+    "dependencies",
+    "dev-dependencies",
+    "dev_dependencies",
+    "build-dependencies",
+    "build_dependencies",
+    "target",
+    "features",
+    "patch",
+    "replace",
+    "profile",
+    "badges",
+    "lints",
+];
+
+pub const PACKAGE_KEYS: &[&str] = &[
+    "name",
+    "version",
+    "authors",
+    "edition",
+    "rust-version",
+    "description",
+    "documentation",
+    "readme",
+    "homepage",
+    "repository",
+    "license",
+    "license-file",
+    "keywords",
+    "categories",
+    "workspace",
+    "build",
+    "links",
+    "exclude",
+    "include",
+    "publish",
+    "metadata",
+    "default-run",
+    "autolib",
+    "autobins",
+    "autoexamples",
+    "autotests",
+    "autobenches",
+    "resolver",
+];
+
+const DEPENDENCY_KEYS: &[&str] = &[
+    "version",
+    "registry",
+    "registry-index",
+    "git",
+    "branch",
+    "tag",
+    "rev",
+    "path",
+    "optional",
+    "default-features",
+    "default_features",
+    "features",
+    "package",
+    "workspace",
+    "public",
+    "artifact",
+    "lib",
+    "target",
+];
+
+const DEPENDENCY_SECTIONS: &[&str] = &[
+    "dependencies",
+    "dev-dependencies",
+    "dev_dependencies",
+    "build-dependencies",
+    "build_dependencies",
+];
+
+const EDITIONS: &[&str] = &["2015", "2018", "2021", "2024"];
+
+#[derive(serde::Serialize)]
+pub struct Diagnostic {
+    path: String,
+    message: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct Report {
+    valid: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Report {
+    pub fn new(diagnostics: Vec<Diagnostic>) -> Self {
+        Self {
+            valid: diagnostics.is_empty(),
+            diagnostics,
+        }
+    }
+
+    pub fn unparseable(error: impl std::fmt::Display) -> Self {
+        Self::new(vec![Diagnostic {
+            path: String::new(),
+            message: format!("could not parse manifest: {error}"),
+        }])
+    }
+}
+
+#[derive(Default)]
+struct Diagnostics(Vec<Diagnostic>);
+
+impl Diagnostics {
+    fn push(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.0.push(Diagnostic {
+            path: path.into(),
+            message: message.into(),
+        });
+    }
+
+    fn unknown_keys(&mut self, path: &str, table: &Value, known: &[&str]) {
+        let Some(table) = table.as_object() else {
+            return;
+        };
+
+        for key in table.keys().filter(|k| !known.contains(&k.as_str())) {
+            self.push(join(path, key), "unknown key");
+        }
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{path}.{key}")
+    }
+}
+
+fn is_inherited(value: &Value) -> bool {
+    value["workspace"] == Value::Bool(true)
+}
+
+pub fn validate(manifest: &Value, rules: &Rules) -> Report {
+    let mut diagnostics = Diagnostics::default();
+
+    if !manifest.is_object() {
+        diagnostics.push("", "manifest must be a table");
+        return Report::new(diagnostics.0);
+    }
+
+    if let Err(e) = serde_json::from_value::<cargo_manifest::Manifest<Metadata>>(manifest.clone()) {
+        diagnostics.push("", format!("invalid manifest: {e}"));
+    }
+
+    diagnostics.unknown_keys("", manifest, MANIFEST_KEYS);
+
+    match &manifest["package"] {
+        Value::Null if manifest["workspace"].is_null() => {
+            diagnostics.push("package", "missing [package] or [workspace] table")
+        }
+        Value::Null => {
+            let workspace = &manifest["workspace"];
+            validate_rules(
+                ("workspace.package", &workspace["package"]),
+                ("workspace.metadata", &workspace["metadata"]),
+                rules,
+                &mut diagnostics,
+            );
+        }
+        package => {
+            validate_package(package, &mut diagnostics);
+            validate_rules(
+                ("package", package),
+                ("package.metadata", &package["metadata"]),
+                rules,
+                &mut diagnostics,
+            );
+        }
+    }
+
+    for section in DEPENDENCY_SECTIONS {
+        validate_dependencies(section, &manifest[section], &mut diagnostics);
+    }
+
+    if let Some(targets) = manifest["target"].as_object() {
+        for (target, table) in targets {
+            for section in DEPENDENCY_SECTIONS {
+                let path = format!("target.{target}.{section}");
+                validate_dependencies(&path, &table[section], &mut diagnostics);
+            }
+        }
+    }
+
+    if let Some(workspace) = manifest["workspace"].get("dependencies") {
+        validate_dependencies("workspace.dependencies", workspace, &mut diagnostics);
+    }
+
+    Report::new(diagnostics.0)
+}
+
+fn validate_package(package: &Value, diagnostics: &mut Diagnostics) {
+    diagnostics.unknown_keys("package", package, PACKAGE_KEYS);
+
+    match package["name"].as_str() {
+        Some(name) => {
+            if let Err(e) = validate_name(name) {
+                diagnostics.push("package.name", e);
+            }
+        }
+        None => diagnostics.push("package.name", "missing package name"),
+    }
+
+    match &package["version"] {
+        Value::String(version) => {
+            if let Err(e) = semver::Version::parse(version) {
+                diagnostics.push("package.version", format!("not a SemVer version: {e}"));
+            }
+        }
+        Value::Null => {}
+        version if is_inherited(version) => {}
+        _ => diagnostics.push("package.version", "expected a string"),
+    }
+
+    match &package["edition"] {
+        Value::String(edition) if !EDITIONS.contains(&edition.as_str()) => diagnostics.push(
+            "package.edition",
+            format!(
+                "unknown edition {edition}, expected one of {}",
+                EDITIONS.join(", ")
+            ),
+        ),
+        Value::String(_) | Value::Null => {}
+        edition if is_inherited(edition) => {}
+        _ => diagnostics.push("package.edition", "expected a string"),
+    }
+
+    match &package["license"] {
+        Value::String(license) => {
+            if let Err(e) = spdx::Expression::parse(license) {
+                diagnostics.push(
+                    "package.license",
+                    format!("invalid SPDX expression: {}", e.reason),
+                );
+            }
+        }
+        Value::Null => {}
+        license if is_inherited(license) => {}
+        _ => diagnostics.push("package.license", "expected a string"),
+    }
+}
+
+// The same checks `Rules::check` applies to workspace members, run on a
+// package table and its metadata. Values inherited from a workspace cannot be
+// resolved in a single manifest and are skipped.
+fn validate_rules(
+    (path, package): (&str, &Value),
+    (metadata_path, metadata): (&str, &Value),
+    rules: &Rules,
+    diagnostics: &mut Diagnostics,
+) {
+    let strings = |key: &str| match &package[key] {
+        Value::Array(values) => Some(values.iter().filter_map(|v| v.as_str()).collect()),
+        value if is_inherited(value) => None,
+        _ => Some(vec![]),
+    };
+
+    if let Some(keywords) = strings("keywords") {
+        let path = join(path, "keywords");
+
+        for keyword in &rules.required_keywords {
+            if !keywords.contains(&keyword.as_str()) {
+                diagnostics.push(&path, format!("magic keyword {keyword:?} not provided"));
+            }
+        }
+
+        for keyword in &rules.forbidden_keywords {
+            if keywords.contains(&keyword.as_str()) {
+                diagnostics.push(&path, format!("forbidden keyword {keyword:?} provided"));
+            }
+        }
+    }
+
+    if let Some(categories) = strings("categories") {
+        let path = join(path, "categories");
+
+        for category in &rules.required_categories {
+            if !categories.contains(&category.as_str()) {
+                diagnostics.push(
+                    &path,
+                    format!("required category {category:?} not provided"),
+                );
+            }
+        }
+
+        if let Some(allowed) = &rules.allowed_categories {
+            for category in categories
+                .iter()
+                .filter(|c| !allowed.iter().any(|a| a == *c))
+            {
+                diagnostics.push(&path, format!("category {category:?} not allowed"));
+            }
+        }
+    }
+
+    let sections = match metadata {
+        metadata if is_inherited(metadata) => return,
+        Value::Object(metadata) => metadata
+            .iter()
+            .filter(|(k, _)| *k != "orders")
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect(),
+        _ => serde_json::Map::new(),
+    };
+
+    for (instance, error) in rules.metadata_errors(&Value::Object(sections)) {
+        diagnostics.push(
+            format!("{metadata_path}{}", instance.replace('/', ".")),
+            format!("invalid metadata: {error}"),
+        );
+    }
+}
+
+fn validate_name(name: &str) -> Result<(), String> {
+    if name.is_empty() {
+        return Err("package name must not be empty".to_string());
+    }
+
+    if name.len() > 64 {
+        return Err("package name must be at most 64 characters".to_string());
+    }
+
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err("package name must not start with a digit".to_string());
+    }
+
+    if let Some(c) = name
+        .chars()
+        .find(|c| !c.is_ascii_alphanumeric() && *c != '-' && *c != '_')
+    {
+        return Err(format!("invalid character {c:?} in package name"));
+    }
+
+    Ok(())
+}
+
+fn validate_dependencies(path: &str, dependencies: &Value, diagnostics: &mut Diagnostics) {
+    let dependencies = match dependencies {
+        Value::Null => return,
+        Value::Object(dependencies) => dependencies,
+        _ => return diagnostics.push(path, "expected a table of dependencies"),
+    };
+
+    for (name, spec) in dependencies {
+        let path = join(path, name);
+
+        match spec {
+            Value::String(version) => validate_requirement(&path, version, diagnostics),
+            Value::Object(table) => {
+                diagnostics.unknown_keys(&path, spec, DEPENDENCY_KEYS);
+
+                if is_inherited(spec) {
+                    continue;
+                }
+
+                match table.get("version") {
+                    Some(Value::String(version)) => {
+                        validate_requirement(&join(&path, "version"), version, diagnostics)
+                    }
+                    Some(_) => diagnostics.push(join(&path, "version"), "expected a string"),
+                    None => {}
+                }
+
+                let sources = ["version", "git", "path"]
+                    .into_iter()
+                    .filter(|s| table.contains_key(*s))
+                    .count();
+                if sources == 0 {
+                    diagnostics.push(&path, "dependency needs a version, git or path");
+                }
+
+                if table.contains_key("git") && table.contains_key("path") {
+                    diagnostics.push(&path, "dependency cannot specify both git and path");
+                }
+
+                let references = ["branch", "tag", "rev"]
+                    .into_iter()
+                    .filter(|r| table.contains_key(*r))
+                    .collect::<Vec<_>>();
+                if !references.is_empty() && !table.contains_key("git") {
+                    diagnostics.push(&path, format!("{} requires git", references.join(", ")));
+                }
+                if references.len() > 1 {
+                    diagnostics.push(
+                        &path,
+                        format!("only one of {} may be specified", references.join(", ")),
+                    );
+                }
+            }
+            _ => diagnostics.push(path, "expected a version string or a table"),
+        }
+    }
+}
+
+fn validate_requirement(path: &str, version: &str, diagnostics: &mut Diagnostics) {
+    if let Err(e) = semver::VersionReq::parse(version) {
+        diagnostics.push(path, format!("not a SemVer requirement: {e}"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use shuttle_runtime::SecretStore;
+
+    use super::*;
+    use crate::day05::Config;
+
+    fn config(profile: Value) -> Config {
+        let secrets = BTreeMap::from([(
+            "DAY05_RULES".to_string(),
+            serde_json::json!({ "strict": profile }).to_string().into(),
+        )]);
+
+        Config::from_secrets(&SecretStore::new(secrets))
+    }
+
+    fn diagnostics(manifest: &str, config: &Config) -> Vec<(String, String)> {
+        let manifest = toml::from_str(manifest).unwrap();
+
+        validate(&manifest, config.rules(Some("strict")).unwrap())
+            .diagnostics
+            .into_iter()
+            .map(|d| (d.path, d.message))
+            .collect()
+    }
+
+    // Checks each manifest reports exactly the expected paths, with messages
+    // starting with the expected text. Serde's own "invalid manifest" errors
+    // are left out as their wording is not ours.
+    fn check_table(cases: &[(&str, &[(&str, &str)])]) {
+        let relaxed = config(serde_json::json!({ "required_keywords": [] }));
+
+        for (manifest, expected) in cases {
+            let found = diagnostics(manifest, &relaxed)
+                .into_iter()
+                .filter(|(path, _)| !path.is_empty())
+                .collect::<Vec<_>>();
+
+            assert_eq!(found.len(), expected.len(), "{manifest}: {found:?}");
+            for ((path, message), (expected_path, expected_message)) in found.iter().zip(*expected)
+            {
+                assert_eq!(path, expected_path, "{manifest}");
+                assert!(
+                    message.starts_with(expected_message),
+                    "{manifest}: {message}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn reports_package_diagnostics() {
+        let long_name = format!("[package]\nname = \"{}\"", "a".repeat(65));
+
+        check_table(&[
+            ("[package]\nname = \"gift\"", &[]),
+            ("[package]\nname = \"gift-box_2\"", &[]),
+            (
+                "[package]\nversion = \"1.0.0\"",
+                &[("package.name", "missing package name")],
+            ),
+            (
+                "[package]\nname = \"\"",
+                &[("package.name", "package name must not be empty")],
+            ),
+            (
+                &long_name,
+                &[("package.name", "package name must be at most 64 characters")],
+            ),
+            (
+                "[package]\nname = \"1gift\"",
+                &[("package.name", "package name must not start with a digit")],
+            ),
+            (
+                "[package]\nname = \"gift box\"",
+                &[("package.name", "invalid character ' ' in package name")],
+            ),
+            ("[package]\nname = \"gift\"\nversion = \"1.0.0-rc.1\"", &[]),
+            ("[package]\nname = \"gift\"\nversion.workspace = true", &[]),
+            (
+                "[package]\nname = \"gift\"\nversion = \"1.0\"",
+                &[("package.version", "not a SemVer version")],
+            ),
+            (
+                "[package]\nname = \"gift\"\nversion = 1",
+                &[("package.version", "expected a string")],
+            ),
+            ("[package]\nname = \"gift\"\nedition = \"2021\"", &[]),
+            ("[package]\nname = \"gift\"\nedition.workspace = true", &[]),
+            (
+                "[package]\nname = \"gift\"\nedition = \"2023\"",
+                &[("package.edition", "unknown edition 2023")],
+            ),
+            (
+                "[package]\nname = \"gift\"\nlicense = \"MIT OR Apache-2.0\"",
+                &[],
+            ),
+            (
+                "[package]\nname = \"gift\"\nlicense = \"MIT OR\"",
+                &[("package.license", "invalid SPDX expression")],
+            ),
+            (
+                "[package]\nname = \"gift\"\nlicense = \"Santa-1.0\"",
+                &[("package.license", "invalid SPDX expression")],
+            ),
+            (
+                "[package]\nname = \"gift\"\ncolour = \"red\"",
+                &[("package.colour", "unknown key")],
+            ),
+            (
+                "[package]\nname = \"gift\"\n[sleigh]\nreindeer = 9",
+                &[("sleigh", "unknown key")],
+            ),
+            (
+                "[dependencies]",
+                &[("package", "missing [package] or [workspace] table")],
+            ),
+        ]);
+    }
+
+    #[test]
+    fn reports_dependency_diagnostics() {
+        check_table(&[
+            (
+                "[package]\nname = \"gift\"\n[dependencies]\nsleigh = \"1.2\"\nbells = { version = \"1\", features = [\"jingle\"] }\nelf = { path = \"../elf\" }\nsnow = { git = \"https://example.com/snow\", tag = \"v1\" }\ntree.workspace = true",
+                &[],
+            ),
+            (
+                "[package]\nname = \"gift\"\n[dependencies]\nsleigh = \"one\"",
+                &[("dependencies.sleigh", "not a SemVer requirement")],
+            ),
+            (
+                "[package]\nname = \"gift\"\n[dependencies]\nsleigh = { version = 1 }",
+                &[("dependencies.sleigh.version", "expected a string")],
+            ),
+            (
+                "[package]\nname = \"gift\"\n[dependencies]\nsleigh = 1",
+                &[("dependencies.sleigh", "expected a version string or a table")],
+            ),
+            (
+                "[package]\nname = \"gift\"\n[dependencies]\nsleigh = { features = [\"fast\"] }",
+                &[("dependencies.sleigh", "dependency needs a version, git or path")],
+            ),
+            (
+                "[package]\nname = \"gift\"\n[dependencies]\nsleigh = { git = \"https://example.com/sleigh\", path = \"../sleigh\" }",
+                &[("dependencies.sleigh", "dependency cannot specify both git and path")],
+            ),
+            (
+                "[package]\nname = \"gift\"\n[dependencies]\nsleigh = { version = \"1\", branch = \"main\" }",
+                &[("dependencies.sleigh", "branch requires git")],
+            ),
+            (
+                "[package]\nname = \"gift\"\n[dependencies]\nsleigh = { path = \"../sleigh\", tag = \"v1\", rev = \"abc\" }",
+                &[
+                    ("dependencies.sleigh", "tag, rev requires git"),
+                    ("dependencies.sleigh", "only one of tag, rev may be specified"),
+                ],
+            ),
+            (
+                "[package]\nname = \"gift\"\n[dependencies]\nsleigh = { version = \"1\", colour = \"red\" }",
+                &[("dependencies.sleigh.colour", "unknown key")],
+            ),
+            (
+                "[package]\nname = \"gift\"\n[target.'cfg(unix)'.dev-dependencies]\nsleigh = { branch = \"main\" }",
+                &[
+                    (
+                        "target.cfg(unix).dev-dependencies.sleigh",
+                        "dependency needs a version, git or path",
+                    ),
+                    ("target.cfg(unix).dev-dependencies.sleigh", "branch requires git"),
+                ],
+            ),
+            (
+                "[workspace]\n[workspace.dependencies]\nsleigh = \"one\"",
+                &[("workspace.dependencies.sleigh", "not a SemVer requirement")],
+            ),
+        ]);
+    }
+
+    #[test]
+    fn reports_unparseable_manifests() {
+        let relaxed = config(serde_json::json!({ "required_keywords": [] }));
+
+        let found = diagnostics("[package]\nname = \"gift\"\nversion = 1", &relaxed);
+        assert!(found
+            .iter()
+            .any(|(path, message)| path.is_empty() && message.starts_with("invalid manifest")));
+    }
+
+    #[test]
+    fn profiles_require_magic_keyword_by_default() {
+        let strict = config(serde_json::json!({}));
+
+        let missing = diagnostics("[package]\nname = \"gift\"", &strict);
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].0, "package.keywords");
+
+        let present = "[package]\nname = \"gift\"\nkeywords = [\"Christmas 2024\"]";
+        assert!(diagnostics(present, &strict).is_empty());
+
+        let relaxed = config(serde_json::json!({ "required_keywords": [] }));
+        assert!(diagnostics("[package]\nname = \"gift\"", &relaxed).is_empty());
+    }
+
+    #[test]
+    fn checks_categories() {
+        let config = config(serde_json::json!({
+            "required_keywords": [],
+            "required_categories": ["toys"],
+            "allowed_categories": ["toys", "games"],
+        }));
+
+        let manifest = "[package]\nname = \"gift\"\ncategories = [\"toys\", \"coal\"]";
+        assert_eq!(
+            diagnostics(manifest, &config),
+            vec![(
+                "package.categories".to_string(),
+                "category \"coal\" not allowed".to_string()
+            )]
+        );
+
+        let manifest = "[package]\nname = \"gift\"\ncategories = [\"games\"]";
+        assert_eq!(
+            diagnostics(manifest, &config),
+            vec![(
+                "package.categories".to_string(),
+                "required category \"toys\" not provided".to_string()
+            )]
+        );
+
+        let manifest = "[package]\nname = \"gift\"\ncategories.workspace = true";
+        assert!(diagnostics(manifest, &config).is_empty());
+    }
+
+    #[test]
+    fn checks_metadata_schema() {
+        let config = config(serde_json::json!({
+            "required_keywords": [],
+            "metadata_schema": {
+                "type": "object",
+                "required": ["sleigh"],
+                "properties": {
+                    "sleigh": {
+                        "type": "object",
+                        "properties": { "reindeer": { "type": "integer" } },
+                    },
+                },
+            },
+        }));
+
+        let manifest = "[package]\nname = \"gift\"\n[package.metadata.sleigh]\nreindeer = 9";
+        assert!(diagnostics(manifest, &config).is_empty());
+
+        let manifest = "[package]\nname = \"gift\"\n[package.metadata.sleigh]\nreindeer = \"nine\"";
+        let found = diagnostics(manifest, &config);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, "package.metadata.sleigh.reindeer");
+
+        let manifest =
+            "[package]\nname = \"gift\"\n[[package.metadata.orders]]\nitem = \"Toy car\"";
+        assert_eq!(diagnostics(manifest, &config).len(), 1);
+
+        let manifest = "[package]\nname = \"gift\"\n[package.metadata]\nworkspace = true";
+        assert!(diagnostics(manifest, &config).is_empty());
+    }
+
+    #[test]
+    fn checks_virtual_manifests() {
+        let config = config(serde_json::json!({ "required_categories": ["toys"] }));
+
+        let manifest = "[workspace]\n[workspace.package]\nkeywords = [\"Christmas 2024\"]";
+        assert_eq!(
+            diagnostics(manifest, &config),
+            vec![(
+                "workspace.package.categories".to_string(),
+                "required category \"toys\" not provided".to_string()
+            )]
+        );
+    }
+}
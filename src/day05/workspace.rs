@@ -0,0 +1,251 @@
+use actix_multipart::form::{bytes::Bytes, MultipartForm};
+use cargo_manifest::{MaybeInherited, Package, Workspace, WorkspacePackage};
+
+use super::{Metadata, Order};
+
+pub type Manifest = cargo_manifest::Manifest<Metadata, Metadata>;
+
+#[derive(MultipartForm)]
+pub struct Upload {
+    pub manifest: Vec<Bytes>,
+}
+
+pub fn content_type(part: &Bytes) -> &str {
+    if let Some(content_type) = &part.content_type {
+        if content_type.essence_str() != "application/octet-stream" {
+            return content_type.essence_str();
+        }
+    }
+
+    match part.file_name.as_deref().and_then(|f| f.rsplit_once('.')) {
+        Some((_, "json")) => "application/json",
+        Some((_, "yaml" | "yml")) => "application/yaml",
+        _ => "application/toml",
+    }
+}
+
+pub struct Member {
+    pub keywords: Vec<String>,
+    pub categories: Vec<String>,
+    pub orders: Option<Vec<Order>>,
+    pub sections: serde_json::Map<String, serde_json::Value>,
+}
+
+// Exactly one manifest may declare [workspace]; every manifest with a
+// [package] becomes a member and inherits from it. A lone virtual manifest
+// is its own member.
+pub fn members(mut manifests: Vec<Manifest>) -> Option<Vec<Member>> {
+    if manifests.iter().filter(|m| m.workspace.is_some()).count() > 1 {
+        return None;
+    }
+
+    let root = manifests.iter_mut().find_map(|m| m.workspace.take());
+
+    let mut members = manifests
+        .into_iter()
+        .filter_map(|m| m.package)
+        .map(|p| member(p, root.as_ref()))
+        .collect::<Option<Vec<_>>>()?;
+
+    if members.is_empty() {
+        let root = root?;
+        let package = root.package.unwrap_or_default();
+        let metadata = root.metadata;
+
+        members.push(Member {
+            keywords: package.keywords.unwrap_or_default(),
+            categories: package.categories.unwrap_or_default(),
+            sections: metadata
+                .as_ref()
+                .map(|m| m.sections.clone())
+                .unwrap_or_default(),
+            orders: metadata.map(|m| m.orders),
+        });
+    }
+
+    Some(members)
+}
+
+// Inherited fields resolve against the root's [workspace.package] and must be
+// present there. A lone manifest has no root to inherit from, so they count as
+// missing instead, as does inherited metadata.
+fn member(package: Package<Metadata>, root: Option<&Workspace<Metadata>>) -> Option<Member> {
+    let inherit = |field: fn(&WorkspacePackage) -> Option<&Vec<String>>| match root {
+        Some(root) => root.package.as_ref().and_then(field).cloned(),
+        None => Some(vec![]),
+    };
+
+    if let (Some(MaybeInherited::Inherited { .. }), Some(root)) = (&package.version, root) {
+        root.package.as_ref()?.version.as_ref()?;
+    }
+
+    let keywords = match package.keywords {
+        None => vec![],
+        Some(MaybeInherited::Local(keywords)) => keywords,
+        Some(MaybeInherited::Inherited { .. }) => inherit(|p| p.keywords.as_ref())?,
+    };
+
+    let categories = match package.categories {
+        None => vec![],
+        Some(MaybeInherited::Local(categories)) => categories,
+        Some(MaybeInherited::Inherited { .. }) => inherit(|p| p.categories.as_ref())?,
+    };
+
+    let metadata = match (package.metadata, root) {
+        (Some(metadata), Some(root)) if metadata.is_inherited() => Some(root.metadata.clone()?),
+        (metadata, _) => metadata,
+    };
+
+    Some(Member {
+        keywords,
+        categories,
+        sections: metadata
+            .as_ref()
+            .map(|m| m.sections.clone())
+            .unwrap_or_default(),
+        orders: metadata.map(|m| m.orders),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROOT: &str = r#"
+[workspace]
+members = ["gift"]
+
+[workspace.package]
+version = "0.1.0"
+keywords = ["Christmas 2024"]
+categories = ["toys"]
+
+[[workspace.metadata.orders]]
+item = "Toy car"
+quantity = 2
+"#;
+
+    fn members(manifests: &[&str]) -> Option<Vec<Member>> {
+        super::members(
+            manifests
+                .iter()
+                .map(|m| toml::from_str(m).unwrap())
+                .collect(),
+        )
+    }
+
+    fn items(member: &Member) -> Vec<&str> {
+        member
+            .orders
+            .iter()
+            .flatten()
+            .map(|o| o.item.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn members_inherit_from_the_root() {
+        let member = r#"
+[package]
+name = "gift"
+version.workspace = true
+keywords.workspace = true
+categories.workspace = true
+
+[package.metadata]
+workspace = true
+"#;
+
+        let members = members(&[member, ROOT]).unwrap();
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].keywords, ["Christmas 2024"]);
+        assert_eq!(members[0].categories, ["toys"]);
+        assert_eq!(items(&members[0]), ["Toy car"]);
+    }
+
+    #[test]
+    fn members_keep_local_fields() {
+        let member = r#"
+[package]
+name = "gift"
+keywords = ["gift"]
+
+[[package.metadata.orders]]
+item = "Doll"
+quantity = 1
+"#;
+
+        let members = members(&[ROOT, member]).unwrap();
+        assert_eq!(members[0].keywords, ["gift"]);
+        assert!(members[0].categories.is_empty());
+        assert_eq!(items(&members[0]), ["Doll"]);
+    }
+
+    #[test]
+    fn every_package_is_a_member() {
+        let gift = "[package]\nname = \"gift\"\nkeywords.workspace = true";
+        let wrapping = "[package]\nname = \"wrapping\"";
+
+        let members = members(&[gift, ROOT, wrapping]).unwrap();
+        assert_eq!(members.len(), 2);
+        assert_eq!(members[0].keywords, ["Christmas 2024"]);
+        assert!(members[1].keywords.is_empty());
+    }
+
+    #[test]
+    fn virtual_manifest_is_its_own_member() {
+        let members = members(&[ROOT]).unwrap();
+
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].keywords, ["Christmas 2024"]);
+        assert_eq!(items(&members[0]), ["Toy car"]);
+    }
+
+    #[test]
+    fn rejects_multiple_roots() {
+        assert!(members(&[ROOT, ROOT]).is_none());
+
+        let package_root = "[package]\nname = \"gift\"\n[workspace]";
+        assert!(members(&[ROOT, package_root]).is_none());
+    }
+
+    #[test]
+    fn rejects_fields_missing_from_the_root() {
+        let root = "[workspace]\n[workspace.package]\nkeywords = [\"Christmas 2024\"]";
+
+        for field in ["version", "categories"] {
+            let member = format!("[package]\nname = \"gift\"\n{field}.workspace = true");
+            assert!(members(&[root, &member]).is_none(), "{field}");
+        }
+
+        let member = "[package]\nname = \"gift\"\n[package.metadata]\nworkspace = true";
+        assert!(members(&[root, member]).is_none());
+        assert!(members(&["[workspace]", member]).is_none());
+    }
+
+    #[test]
+    fn lone_manifests_have_nothing_to_inherit() {
+        let member = r#"
+[package]
+name = "gift"
+version.workspace = true
+keywords.workspace = true
+
+[package.metadata]
+workspace = true
+"#;
+
+        let members = members(&[member]).unwrap();
+        assert!(members[0].keywords.is_empty());
+        assert!(items(&members[0]).is_empty());
+    }
+
+    #[test]
+    fn only_true_marks_inherited_metadata() {
+        let member = "[package]\nname = \"gift\"\n[package.metadata]\nworkspace = \"yes\"";
+
+        let members = members(&[ROOT, member]).unwrap();
+        assert!(items(&members[0]).is_empty());
+        assert_eq!(members[0].sections["workspace"], "yes");
+    }
+}
@@ -1,99 +1,539 @@
-use std::{sync::LazyLock, time::Duration};
+mod format;
+mod ledger;
+mod units;
 
-use actix_web::{http, post, web, HttpRequest, HttpResponse};
-use shuttle_runtime::tokio::sync::Mutex;
+use std::time::{Duration, Instant};
 
-static STATE: LazyLock<web::Data<State>> = LazyLock::new(Default::default);
+use actix_web::{get, http, post, put, web, HttpRequest, HttpResponse};
+use shuttle_runtime::tokio::sync::Semaphore;
 
-struct State {
-    rate_limiter: Mutex<leaky_bucket::RateLimiter>,
-}
+use crate::rate_limit::{self, bucket, client, rate_limited};
+use format::Format;
 
-fn rate_limiter() -> leaky_bucket::RateLimiter {
-    leaky_bucket::Builder::default()
-        .initial(5)
-        .interval(Duration::from_secs(1))
-        .max(5)
-        .build()
-}
+const MILK: bucket::Policy = bucket::Policy {
+    capacity: 5,
+    refill: 1,
+    interval: Duration::from_secs(1),
+    cost: 1,
+};
 
-impl Default for State {
-    fn default() -> Self {
-        let rate_limiter = Mutex::new(rate_limiter());
-        Self { rate_limiter }
-    }
+pub struct State {
+    bucket: Box<dyn bucket::Backend>,
+    keys: client::Keys,
+    admin_token: Option<String>,
+    decimals: Option<u32>,
+    max_wait: Duration,
+    waiters: Semaphore,
+    max_batch: usize,
 }
 
-pub fn configure(cfg: &mut web::ServiceConfig) {
-    let state = STATE.clone();
+impl State {
+    pub async fn from_secrets(secrets: &shuttle_runtime::SecretStore, pool: sqlx::PgPool) -> Self {
+        let policy = match sqlx::query!(
+            "SELECT capacity, refill, interval_ms FROM milk_settings WHERE name = 'milk'"
+        )
+        .fetch_optional(&pool)
+        .await
+        .unwrap()
+        {
+            Some(settings) => bucket::Policy {
+                capacity: settings.capacity as usize,
+                refill: settings.refill as usize,
+                interval: Duration::from_millis(settings.interval_ms as u64),
+                ..MILK
+            },
+            None => MILK,
+        };
 
-    cfg.service(post_milk).service(post_refill).app_data(state);
-}
+        let bucket: Box<dyn bucket::Backend> =
+            match secrets.get("DAY09_RATE_LIMIT_BACKEND").as_deref() {
+                Some("postgres") => Box::new(bucket::Postgres::new("milk", policy, pool)),
+                _ => {
+                    let max_buckets = secrets
+                        .get("DAY09_MAX_BUCKETS")
+                        .and_then(|m| m.parse().ok())
+                        .unwrap_or(10_000);
+                    let idle = secrets
+                        .get("DAY09_BUCKET_IDLE")
+                        .and_then(|i| i.parse().ok())
+                        .map(Duration::from_secs)
+                        .unwrap_or(Duration::from_secs(10 * 60));
 
-#[derive(serde::Serialize, serde::Deserialize)]
-#[serde(rename_all = "snake_case")]
-enum Unit {
-    Liters(f32),
-    Gallons(f32),
-    Litres(f32),
-    Pints(f32),
-}
-
-impl Unit {
-    fn convert(self) -> Unit {
-        match self {
-            Unit::Liters(liters) => {
-                let gallons = liters / 3.785_411_8;
-                Unit::Gallons(gallons)
-            }
-            Unit::Gallons(gallons) => {
-                let liters = gallons * 3.785_411_8;
-                Unit::Liters(liters)
-            }
-            Unit::Litres(litres) => {
-                let pints = litres * 1.759_754;
-                Unit::Pints(pints)
-            }
-            Unit::Pints(pints) => {
-                let litres = pints / 1.759_754;
-                Unit::Litres(litres)
-            }
+                    Box::new(bucket::Memory::new(policy, max_buckets, idle))
+                }
+            };
+        let keys = client::Keys::from_secrets(secrets);
+        let admin_token = secrets.get("DAY09_ADMIN_TOKEN");
+        let decimals = secrets.get("DAY09_DECIMALS").and_then(|d| d.parse().ok());
+        let max_wait = secrets
+            .get("DAY09_MAX_WAIT")
+            .and_then(|w| w.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(Duration::from_secs(10));
+        let max_waiters = secrets
+            .get("DAY09_MAX_WAITERS")
+            .and_then(|m| m.parse().ok())
+            .unwrap_or(32);
+        let max_batch = secrets
+            .get("DAY09_MAX_BATCH")
+            .and_then(|m| m.parse().ok())
+            .unwrap_or(100);
+
+        Self {
+            bucket,
+            keys,
+            admin_token,
+            decimals,
+            max_wait,
+            waiters: Semaphore::new(max_waiters),
+            max_batch,
         }
     }
 }
 
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(post_milk)
+        .service(post_refill)
+        .service(get_bucket)
+        .service(get_settings)
+        .service(put_settings)
+        .service(ledger::get_ledger)
+        .service(ledger::get_daily);
+}
+
 #[post("/9/milk")]
 async fn post_milk(
     req: HttpRequest,
     state: web::Data<State>,
-    unit: Option<web::Json<Unit>>,
+    pool: web::Data<sqlx::PgPool>,
+    body: web::Bytes,
 ) -> HttpResponse {
-    let content_type = req.headers().get(http::header::CONTENT_TYPE);
+    let request_format = Format::from_request(&req);
+    let response_format = Format::accepted(&req, request_format.unwrap_or(Format::Json));
 
-    let json = matches!(
-        content_type.map(|c| c.to_str()),
-        Some(Ok("application/json"))
-    );
+    let key = state.keys.key(&req);
+    let deadline = wait(&req, &state).map(|w| Instant::now() + w);
 
-    if !state.rate_limiter.lock().await.try_acquire(1) {
-        HttpResponse::TooManyRequests().body("No milk available\n")
-    } else if !json {
-        HttpResponse::Ok().body("Milk withdrawn\n")
-    } else {
-        match unit.map(|j| j.into_inner()) {
-            Some(unit) => {
-                let unit = unit.convert();
+    let withdrawal = match request_format.map(|f| f.parse(&body)) {
+        Some(Ok(units::Request::Batch(batch))) => {
+            return withdraw_batch(batch, &key, deadline, response_format, &state, &pool).await
+        }
+        Some(Ok(units::Request::Single(withdrawal))) => Some(Ok(withdrawal)),
+        Some(Err(e)) => Some(Err(e)),
+        None => None,
+    };
 
-                HttpResponse::Ok().json(unit)
-            }
-            _ => HttpResponse::BadRequest().finish(),
+    // Invalid withdrawals are still charged the flat cost so they cannot be
+    // sent for free.
+    let policy = state.bucket.policy();
+    let withdrawal = withdrawal.map(|w| {
+        w.and_then(|w| w.convert())
+            .and_then(|q| Ok((cost(&q, &policy)?, q)))
+    });
+    let cost = match &withdrawal {
+        Some(Ok((cost, _))) => *cost,
+        _ => policy.cost,
+    };
+    let status = acquire(&key, cost, deadline, &state).await;
+
+    if !status.acquired {
+        return rate_limit::too_many_requests(&status, cost).body("No milk available\n");
+    }
+
+    match withdrawal {
+        None => {
+            ledger::withdrawal(&key, cost, None, &pool).await;
+
+            rate_limited(&mut HttpResponse::Ok(), &status).body("Milk withdrawn\n")
+        }
+        Some(Ok((cost, quantity))) => {
+            let quantity = quantity.round(state.decimals);
+            ledger::withdrawal(&key, cost, Some(&quantity), &pool).await;
+
+            response_format.respond(rate_limited(&mut HttpResponse::Ok(), &status), &quantity)
         }
+        Some(Err(e)) => rate_limited(&mut HttpResponse::BadRequest(), &status)
+            .body(format!("Invalid withdrawal: {e}\n")),
     }
 }
 
+#[derive(serde::Deserialize)]
+struct Wait {
+    wait: Option<u64>,
+}
+
+fn wait(req: &HttpRequest, state: &State) -> Option<Duration> {
+    let query = web::Query::<Wait>::from_query(req.query_string())
+        .ok()
+        .and_then(|q| q.wait);
+
+    let prefer = req
+        .headers()
+        .get_all("prefer")
+        .filter_map(|p| p.to_str().ok())
+        .flat_map(|p| p.split([',', ';']))
+        .find_map(|p| p.trim().strip_prefix("wait=")?.trim().parse::<u64>().ok());
+
+    query
+        .or(prefer)
+        .filter(|&w| w > 0)
+        .map(|w| Duration::from_secs(w).min(state.max_wait))
+}
+
+async fn acquire(
+    key: &str,
+    permits: usize,
+    deadline: Option<Instant>,
+    state: &State,
+) -> bucket::Status {
+    let Some(deadline) = deadline else {
+        return state.bucket.try_acquire(key, permits).await;
+    };
+
+    match state.waiters.try_acquire() {
+        Ok(_waiter) => {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            state.bucket.acquire(key, permits, timeout).await
+        }
+        Err(_) => state.bucket.try_acquire(key, permits).await,
+    }
+}
+
+// Withdrawals, single or batched, are charged per started liter so that large
+// ones drain the bucket faster than small ones.
+fn cost(quantity: &units::Quantity, policy: &bucket::Policy) -> Result<usize, String> {
+    let cost = (quantity.liters().ceil() as usize)
+        .max(1)
+        .saturating_mul(policy.cost);
+
+    if cost > policy.capacity {
+        return Err(format!(
+            "withdrawal needs {cost} tokens but the bucket holds at most {}",
+            policy.capacity
+        ));
+    }
+
+    Ok(cost)
+}
+
+async fn withdraw_batch(
+    batch: Vec<units::Withdrawal>,
+    key: &str,
+    deadline: Option<Instant>,
+    format: Format,
+    state: &State,
+    pool: &sqlx::PgPool,
+) -> HttpResponse {
+    if batch.is_empty() || batch.len() > state.max_batch {
+        let status = state.bucket.status(key).await;
+        let error = if batch.is_empty() {
+            "Invalid withdrawal: empty batch\n".to_string()
+        } else {
+            format!(
+                "Invalid withdrawal: at most {} withdrawals per batch\n",
+                state.max_batch
+            )
+        };
+
+        return rate_limited(&mut HttpResponse::BadRequest(), &status).body(error);
+    }
+
+    let policy = state.bucket.policy();
+    let mut status = None;
+    let mut dry = None;
+    let mut outcomes = Vec::with_capacity(batch.len());
+
+    for withdrawal in batch {
+        let outcome = match withdrawal
+            .convert()
+            .and_then(|q| Ok((cost(&q, &policy)?, q)))
+        {
+            Err(error) => units::Outcome::Invalid { error },
+            Ok(_) if dry.is_some() => units::Outcome::NoMilk,
+            Ok((cost, quantity)) => {
+                let acquired = acquire(key, cost, deadline, state).await;
+                if !acquired.acquired {
+                    dry = Some(cost);
+                }
+                status = Some(acquired);
+
+                if dry.is_some() {
+                    units::Outcome::NoMilk
+                } else {
+                    let quantity = quantity.round(state.decimals);
+                    ledger::withdrawal(key, cost, Some(&quantity), pool).await;
+
+                    units::Outcome::Withdrawn { quantity }
+                }
+            }
+        };
+
+        outcomes.push(outcome);
+    }
+
+    let status = match status {
+        Some(status) => status,
+        None => state.bucket.status(key).await,
+    };
+
+    let withdrawn = outcomes
+        .iter()
+        .filter(|o| matches!(o, units::Outcome::Withdrawn { .. }))
+        .count();
+
+    if withdrawn == outcomes.len() {
+        format.respond(rate_limited(&mut HttpResponse::Ok(), &status), &outcomes)
+    } else if withdrawn > 0 {
+        format.respond(
+            rate_limited(&mut HttpResponse::MultiStatus(), &status),
+            &outcomes,
+        )
+    } else if let Some(cost) = dry {
+        format.respond(&mut rate_limit::too_many_requests(&status, cost), &outcomes)
+    } else {
+        format.respond(
+            rate_limited(&mut HttpResponse::BadRequest(), &status),
+            &outcomes,
+        )
+    }
+}
+
+#[derive(serde::Serialize)]
+struct Bucket {
+    limit: usize,
+    remaining: usize,
+    reset: u64,
+}
+
+#[get("/9/bucket")]
+async fn get_bucket(req: HttpRequest, state: web::Data<State>) -> HttpResponse {
+    let status = state.bucket.status(&state.keys.key(&req)).await;
+
+    let bucket = Bucket {
+        limit: status.limit,
+        remaining: status.remaining,
+        reset: status.reset().as_secs_f64().ceil() as u64,
+    };
+
+    rate_limited(&mut HttpResponse::Ok(), &status).json(bucket)
+}
+
+#[derive(serde::Deserialize)]
+struct Refill {
+    amount: usize,
+}
+
+// An empty body refills the bucket completely; anything else has to be a
+// valid amount.
+fn refill_amount(body: &[u8]) -> Result<Option<usize>, serde_json::Error> {
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Ok(None);
+    }
+
+    serde_json::from_slice::<Refill>(body).map(|r| Some(r.amount))
+}
+
 #[post("/9/refill")]
-async fn post_refill(state: web::Data<State>) -> HttpResponse {
-    *state.rate_limiter.lock().await = rate_limiter();
+async fn post_refill(
+    req: HttpRequest,
+    state: web::Data<State>,
+    pool: web::Data<sqlx::PgPool>,
+    body: web::Bytes,
+) -> HttpResponse {
+    let amount = match refill_amount(&body) {
+        Ok(amount) => amount,
+        Err(e) => return HttpResponse::BadRequest().body(format!("Invalid refill: {e}\n")),
+    };
+
+    let key = state.keys.key(&req);
+
+    state.bucket.refill(&key, amount).await;
+    ledger::refill(&key, amount, &pool).await;
 
     HttpResponse::Ok().finish()
 }
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Settings {
+    capacity: usize,
+    refill: usize,
+    interval_ms: u64,
+}
+
+fn is_admin(req: &HttpRequest, state: &State) -> bool {
+    let Some(admin_token) = &state.admin_token else {
+        return false;
+    };
+
+    req.headers()
+        .get(http::header::AUTHORIZATION)
+        .and_then(|a| a.to_str().ok())
+        .and_then(|a| a.strip_prefix("Bearer "))
+        .is_some_and(|t| t == admin_token)
+}
+
+#[get("/9/settings")]
+async fn get_settings(req: HttpRequest, state: web::Data<State>) -> HttpResponse {
+    if !is_admin(&req, &state) {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    let policy = state.bucket.policy();
+
+    let settings = Settings {
+        capacity: policy.capacity,
+        refill: policy.refill,
+        interval_ms: policy.interval.as_millis() as u64,
+    };
+
+    HttpResponse::Ok().json(settings)
+}
+
+#[put("/9/settings")]
+async fn put_settings(
+    req: HttpRequest,
+    settings: web::Json<Settings>,
+    state: web::Data<State>,
+    pool: web::Data<sqlx::PgPool>,
+) -> HttpResponse {
+    if !is_admin(&req, &state) {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    let settings = settings.into_inner();
+    if settings.capacity == 0 || settings.refill == 0 || settings.interval_ms == 0 {
+        return HttpResponse::BadRequest().finish();
+    }
+
+    sqlx::query!(
+        "INSERT INTO milk_settings(name, capacity, refill, interval_ms) VALUES('milk', $1, $2, $3) ON CONFLICT (name) DO UPDATE SET capacity = $1, refill = $2, interval_ms = $3",
+        settings.capacity as i64,
+        settings.refill as i64,
+        settings.interval_ms as i64
+    )
+    .execute(pool.as_ref())
+    .await
+    .unwrap();
+
+    let policy = bucket::Policy {
+        capacity: settings.capacity,
+        refill: settings.refill,
+        interval: Duration::from_millis(settings.interval_ms),
+        ..state.bucket.policy()
+    };
+    state.bucket.set_policy(policy).await;
+
+    HttpResponse::Ok().json(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_refill_is_full() {
+        assert_eq!(refill_amount(b"").unwrap(), None);
+        assert_eq!(refill_amount(b" \n").unwrap(), None);
+    }
+
+    #[test]
+    fn parses_refill_amount() {
+        assert_eq!(refill_amount(br#"{"amount": 2}"#).unwrap(), Some(2));
+        assert_eq!(
+            refill_amount(br#"{"amount": 18446744073709551615}"#).unwrap(),
+            Some(usize::MAX)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_refill() {
+        assert!(refill_amount(br#"{"amount": -1}"#).is_err());
+        assert!(refill_amount(br#"{"amount": 1.5}"#).is_err());
+        assert!(refill_amount(br#"{"amt": 2}"#).is_err());
+        assert!(refill_amount(b"2").is_err());
+    }
+
+    fn state(max_batch: usize) -> State {
+        State {
+            bucket: Box::new(bucket::Memory::new(MILK, 10, Duration::from_secs(60))),
+            keys: client::Keys::from_secrets(
+                &shuttle_runtime::SecretStore::new(Default::default()),
+            ),
+            admin_token: None,
+            decimals: None,
+            max_wait: Duration::ZERO,
+            waiters: Semaphore::new(1),
+            max_batch,
+        }
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs DATABASE_URL"]
+    async fn charges_single_withdrawals_per_liter(pool: sqlx::PgPool) {
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(web::Data::new(state(1)))
+                .app_data(web::Data::new(pool))
+                .service(post_milk),
+        )
+        .await;
+
+        let milk = |body: &'static str| {
+            actix_web::test::TestRequest::post()
+                .uri("/9/milk")
+                .insert_header((http::header::CONTENT_TYPE, "application/json"))
+                .peer_addr("10.0.0.1:4000".parse().unwrap())
+                .set_payload(body)
+                .to_request()
+        };
+
+        let res = actix_web::test::call_service(&app, milk(r#"{"liters": 2.5}"#)).await;
+        assert_eq!(res.status(), http::StatusCode::OK);
+        assert_eq!(res.headers().get("ratelimit-remaining").unwrap(), "2");
+
+        let res = actix_web::test::call_service(&app, milk(r#"{"liters": 9}"#)).await;
+        assert_eq!(res.status(), http::StatusCode::BAD_REQUEST);
+        assert_eq!(res.headers().get("ratelimit-remaining").unwrap(), "1");
+
+        let res = actix_web::test::call_service(&app, milk(r#"{"liters": 2}"#)).await;
+        assert_eq!(res.status(), http::StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[actix_web::test]
+    async fn rejects_invalid_batches_with_rate_limit_headers() {
+        let state = state(1);
+        let pool = sqlx::PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+        let withdrawal = || serde_json::from_str(r#"{"liters": 1}"#).unwrap();
+
+        for batch in [vec![], vec![withdrawal(), withdrawal()]] {
+            let res = withdraw_batch(batch, "elf", None, Format::Json, &state, &pool).await;
+
+            assert_eq!(res.status(), http::StatusCode::BAD_REQUEST);
+            assert_eq!(res.headers().get("ratelimit-limit").unwrap(), "5");
+            assert_eq!(res.headers().get("ratelimit-remaining").unwrap(), "5");
+        }
+    }
+
+    fn quantity(unit: units::Unit, value: f64) -> units::Quantity {
+        units::Quantity { unit, value }
+    }
+
+    #[test]
+    fn charges_per_started_liter() {
+        assert_eq!(cost(&quantity(units::Unit::Liters, 0.0), &MILK), Ok(1));
+        assert_eq!(cost(&quantity(units::Unit::Ml, 250.0), &MILK), Ok(1));
+        assert_eq!(cost(&quantity(units::Unit::Liters, 1.0), &MILK), Ok(1));
+        assert_eq!(cost(&quantity(units::Unit::Liters, 1.5), &MILK), Ok(2));
+        assert_eq!(cost(&quantity(units::Unit::Gallons, 1.0), &MILK), Ok(4));
+
+        let policy = bucket::Policy { cost: 2, ..MILK };
+        assert_eq!(cost(&quantity(units::Unit::Liters, 2.0), &policy), Ok(4));
+    }
+
+    #[test]
+    fn rejects_withdrawals_larger_than_bucket() {
+        assert!(cost(&quantity(units::Unit::Liters, 5.0), &MILK).is_ok());
+        assert!(cost(&quantity(units::Unit::Liters, 5.1), &MILK).is_err());
+        assert!(cost(&quantity(units::Unit::Liters, 1e300), &MILK).is_err());
+    }
+}
@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+
+use actix_web::{
+    http::{self, header::Header},
+    HttpRequest, HttpResponse, HttpResponseBuilder,
+};
+
+use super::units;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Xml,
+    Form,
+    Cbor,
+}
+
+impl Format {
+    fn from_mime(mime: &str) -> Option<Format> {
+        match mime.split(';').next().unwrap_or_default().trim() {
+            "application/json" => Some(Format::Json),
+            "application/xml" | "text/xml" => Some(Format::Xml),
+            "application/x-www-form-urlencoded" => Some(Format::Form),
+            "application/cbor" => Some(Format::Cbor),
+            _ => None,
+        }
+    }
+
+    pub fn from_request(req: &HttpRequest) -> Option<Format> {
+        req.headers()
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|c| c.to_str().ok())
+            .and_then(Format::from_mime)
+    }
+
+    pub fn accepted(req: &HttpRequest, default: Format) -> Format {
+        let Ok(accept) = http::header::Accept::parse(req) else {
+            return default;
+        };
+
+        accept
+            .ranked()
+            .iter()
+            .find_map(|m| Format::from_mime(m.essence_str()))
+            .unwrap_or(default)
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::Xml => "application/xml",
+            Format::Form => "application/x-www-form-urlencoded",
+            Format::Cbor => "application/cbor",
+        }
+    }
+
+    pub fn parse(self, body: &[u8]) -> Result<units::Request, String> {
+        let invalid = "expected a unit object or an array of them".to_string();
+
+        match self {
+            Format::Json => serde_json::from_slice(body).map_err(|_| invalid),
+            Format::Cbor => ciborium::from_reader(body).map_err(|_| invalid),
+            Format::Form => serde_urlencoded::from_bytes::<HashMap<String, String>>(body)
+                .map_err(|_| invalid)
+                .and_then(units::Withdrawal::from_fields)
+                .map(units::Request::Single),
+            Format::Xml => quick_xml::de::from_reader::<_, HashMap<String, String>>(body)
+                .map_err(|_| invalid)
+                .and_then(units::Withdrawal::from_fields)
+                .map(units::Request::Single),
+        }
+    }
+
+    fn serialize<T: serde::Serialize>(self, value: &T) -> Option<Vec<u8>> {
+        match self {
+            Format::Json => serde_json::to_vec(value).ok(),
+            Format::Cbor => {
+                let mut body = vec![];
+                ciborium::into_writer(value, &mut body).ok()?;
+                Some(body)
+            }
+            Format::Form => serde_urlencoded::to_string(value)
+                .ok()
+                .map(String::into_bytes),
+            Format::Xml => quick_xml::se::to_string_with_root("milk", value)
+                .ok()
+                .map(String::into_bytes),
+        }
+    }
+
+    // Batches have no form representation, so anything that cannot be
+    // serialized in the negotiated format falls back to JSON.
+    pub fn respond<T: serde::Serialize>(
+        self,
+        response: &mut HttpResponseBuilder,
+        value: &T,
+    ) -> HttpResponse {
+        let (format, body) = match self.serialize(value) {
+            Some(body) => (self, body),
+            None => (Format::Json, serde_json::to_vec(value).unwrap()),
+        };
+
+        response
+            .insert_header((http::header::CONTENT_TYPE, format.content_type()))
+            .body(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    const FORMATS: [Format; 4] = [Format::Json, Format::Xml, Format::Form, Format::Cbor];
+
+    fn accepted(accept: &str) -> Format {
+        let req = TestRequest::default()
+            .insert_header((http::header::ACCEPT, accept))
+            .to_http_request();
+
+        Format::accepted(&req, Format::Json)
+    }
+
+    #[test]
+    fn round_trips_every_format() {
+        for format in FORMATS {
+            let quantity = units::Quantity {
+                unit: units::Unit::Gallons,
+                value: 2.5,
+            };
+            let body = format.serialize(&quantity).unwrap();
+
+            let Ok(units::Request::Single(withdrawal)) = format.parse(&body) else {
+                panic!("{format:?} did not parse back");
+            };
+            let quantity = withdrawal.convert().unwrap();
+
+            assert_eq!(quantity.unit, units::Unit::Liters, "{format:?}");
+            assert!(
+                (quantity.value - 2.5 * 3.785_411_784).abs() < 1e-9,
+                "{format:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn parses_batches() {
+        let batch = serde_json::json!([{ "liters": 1 }, { "gallons": 2 }]);
+
+        let json = serde_json::to_vec(&batch).unwrap();
+        let mut cbor = vec![];
+        ciborium::into_writer(&batch, &mut cbor).unwrap();
+
+        for (format, body) in [(Format::Json, json), (Format::Cbor, cbor)] {
+            let Ok(units::Request::Batch(batch)) = format.parse(&body) else {
+                panic!("{format:?} did not parse a batch");
+            };
+
+            assert_eq!(batch.len(), 2);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_bodies() {
+        for format in FORMATS {
+            assert!(format.parse(b"\xff{liters").is_err(), "{format:?}");
+        }
+    }
+
+    #[test]
+    fn reads_content_type() {
+        let format = |content_type: &str| {
+            let req = TestRequest::default()
+                .insert_header((http::header::CONTENT_TYPE, content_type))
+                .to_http_request();
+
+            Format::from_request(&req)
+        };
+
+        assert_eq!(
+            format("application/json; charset=utf-8"),
+            Some(Format::Json)
+        );
+        assert_eq!(format("text/xml"), Some(Format::Xml));
+        assert_eq!(format("text/plain"), None);
+        assert_eq!(
+            Format::from_request(&TestRequest::default().to_http_request()),
+            None
+        );
+    }
+
+    #[test]
+    fn accept_overrides_request_format() {
+        assert_eq!(accepted("application/xml"), Format::Xml);
+        assert_eq!(accepted("application/cbor"), Format::Cbor);
+        assert_eq!(
+            accepted("application/json;q=0.5, application/x-www-form-urlencoded"),
+            Format::Form
+        );
+        assert_eq!(accepted("text/html, application/cbor;q=0.1"), Format::Cbor);
+    }
+
+    #[test]
+    fn unknown_accept_keeps_default() {
+        assert_eq!(accepted("text/html"), Format::Json);
+        assert_eq!(accepted("*/*"), Format::Json);
+
+        let req = TestRequest::default().to_http_request();
+        assert_eq!(Format::accepted(&req, Format::Cbor), Format::Cbor);
+    }
+
+    #[test]
+    fn responds_with_json_when_format_cannot_represent_value() {
+        let outcomes = vec![units::Outcome::NoMilk, units::Outcome::NoMilk];
+
+        let res = Format::Form.respond(&mut HttpResponse::Ok(), &outcomes);
+        assert_eq!(
+            res.headers().get(http::header::CONTENT_TYPE).unwrap(),
+            "application/json"
+        );
+
+        let res = Format::Cbor.respond(&mut HttpResponse::Ok(), &outcomes);
+        assert_eq!(
+            res.headers().get(http::header::CONTENT_TYPE).unwrap(),
+            "application/cbor"
+        );
+    }
+}
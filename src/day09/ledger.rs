@@ -0,0 +1,145 @@
+use actix_web::{get, web, HttpRequest, HttpResponse};
+
+use super::{is_admin, units, State};
+
+pub async fn withdrawal(
+    client: &str,
+    tokens: usize,
+    quantity: Option<&units::Quantity>,
+    pool: &sqlx::PgPool,
+) {
+    let unit = quantity.map(|q| unit_name(q.unit));
+    let amount = quantity.map(|q| q.value);
+
+    sqlx::query!(
+        "INSERT INTO milk_ledger(kind, client, tokens, unit, amount) VALUES('withdrawal', $1, $2, $3, $4)",
+        client,
+        tokens as i64,
+        unit,
+        amount
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+pub async fn refill(client: &str, tokens: Option<usize>, pool: &sqlx::PgPool) {
+    sqlx::query!(
+        "INSERT INTO milk_ledger(kind, client, tokens) VALUES('refill', $1, $2)",
+        client,
+        tokens.map(|t| t as i64)
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+fn unit_name(unit: units::Unit) -> String {
+    serde_json::to_value(unit)
+        .unwrap()
+        .as_str()
+        .unwrap()
+        .to_string()
+}
+
+#[derive(serde::Deserialize)]
+struct Page {
+    limit: Option<i64>,
+    before: Option<i64>,
+}
+
+#[derive(serde::Serialize)]
+struct Entry {
+    id: i64,
+    kind: String,
+    client: String,
+    tokens: Option<i64>,
+    unit: Option<String>,
+    amount: Option<f64>,
+    recorded_at: String,
+}
+
+#[get("/9/ledger")]
+pub async fn get_ledger(
+    req: HttpRequest,
+    page: web::Query<Page>,
+    state: web::Data<State>,
+    pool: web::Data<sqlx::PgPool>,
+) -> HttpResponse {
+    if !is_admin(&req, &state) {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    let limit = page.limit.unwrap_or(100).clamp(1, 1000);
+
+    let entries: Vec<Entry> = sqlx::query!(
+        "SELECT id, kind, client, tokens, unit, amount, recorded_at FROM milk_ledger WHERE $1::bigint IS NULL OR id < $1 ORDER BY id DESC LIMIT $2",
+        page.before,
+        limit
+    )
+    .fetch_all(pool.as_ref())
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|e| Entry {
+        id: e.id,
+        kind: e.kind,
+        client: e.client,
+        tokens: e.tokens,
+        unit: e.unit,
+        amount: e.amount,
+        recorded_at: e.recorded_at.to_rfc3339(),
+    })
+    .collect();
+
+    HttpResponse::Ok().json(entries)
+}
+
+#[derive(serde::Deserialize)]
+struct Days {
+    days: Option<i32>,
+}
+
+#[derive(serde::Serialize)]
+struct Total {
+    day: String,
+    kind: String,
+    unit: Option<String>,
+    count: i64,
+    tokens: i64,
+    amount: Option<f64>,
+}
+
+#[get("/9/ledger/daily")]
+pub async fn get_daily(
+    req: HttpRequest,
+    days: web::Query<Days>,
+    state: web::Data<State>,
+    pool: web::Data<sqlx::PgPool>,
+) -> HttpResponse {
+    if !is_admin(&req, &state) {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    let days = days.days.unwrap_or(30).clamp(1, 366);
+
+    let totals: Vec<Total> = sqlx::query!(
+        r#"SELECT recorded_at::date AS "day!", kind, unit, COUNT(*) AS "count!", COALESCE(SUM(tokens), 0)::bigint AS "tokens!", SUM(amount) AS amount FROM milk_ledger WHERE recorded_at >= CURRENT_DATE - $1::int GROUP BY 1, kind, unit ORDER BY 1 DESC, kind, unit"#,
+        days
+    )
+    .fetch_all(pool.as_ref())
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|t| Total {
+        day: t.day.to_string(),
+        kind: t.kind,
+        unit: t.unit,
+        count: t.count,
+        tokens: t.tokens,
+        amount: t.amount,
+    })
+    .collect();
+
+    HttpResponse::Ok().json(totals)
+}
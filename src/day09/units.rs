@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Unit {
+    Ml,
+    Cl,
+    Liters,
+    Litres,
+    FlOz,
+    ImperialFlOz,
+    Cups,
+    UsPints,
+    Pints,
+    Quarts,
+    ImperialQuarts,
+    Gallons,
+    ImperialGallons,
+}
+
+impl Unit {
+    fn liters(self) -> f64 {
+        match self {
+            Unit::Ml => 0.001,
+            Unit::Cl => 0.01,
+            Unit::Liters | Unit::Litres => 1.0,
+            Unit::FlOz => 0.029_573_529_562_5,
+            Unit::ImperialFlOz => 0.028_413_062_5,
+            Unit::Cups => 0.236_588_236_5,
+            Unit::UsPints => 0.473_176_473,
+            Unit::Pints => 0.568_261_25,
+            Unit::Quarts => 0.946_352_946,
+            Unit::ImperialQuarts => 1.136_522_5,
+            Unit::Gallons => 3.785_411_784,
+            Unit::ImperialGallons => 4.546_09,
+        }
+    }
+
+    // `liters` and `gallons` are the US pair and `litres` and `pints` the
+    // imperial one; every other unit converts to its metric counterpart.
+    fn paired(self) -> Unit {
+        match self {
+            Unit::Liters => Unit::Gallons,
+            Unit::Gallons => Unit::Liters,
+            Unit::Litres => Unit::Pints,
+            Unit::Pints => Unit::Litres,
+            Unit::Ml | Unit::Cl | Unit::FlOz | Unit::Cups | Unit::UsPints | Unit::Quarts => {
+                Unit::Liters
+            }
+            Unit::ImperialFlOz | Unit::ImperialQuarts | Unit::ImperialGallons => Unit::Litres,
+        }
+    }
+}
+
+pub struct Quantity {
+    pub unit: Unit,
+    pub value: f64,
+}
+
+impl Quantity {
+    pub fn convert(self, to: Option<Unit>) -> Quantity {
+        let to = to.unwrap_or(self.unit.paired());
+        let value = self.value * self.unit.liters() / to.liters();
+
+        Quantity { unit: to, value }
+    }
+
+    pub fn liters(&self) -> f64 {
+        self.value * self.unit.liters()
+    }
+
+    // Digits beyond what an f64 holds are left alone, as are values too large
+    // to scale, which would otherwise round to infinity or NaN.
+    pub fn round(self, decimals: Option<u32>) -> Quantity {
+        let Some(decimals) = decimals else {
+            return self;
+        };
+
+        let factor = 10_f64.powi(decimals.min(f64::DIGITS) as i32);
+        let scaled = self.value * factor;
+        if !scaled.is_finite() {
+            return self;
+        }
+
+        Quantity {
+            value: scaled.round() / factor,
+            ..self
+        }
+    }
+}
+
+impl serde::Serialize for Quantity {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry(&self.unit, &self.value)?;
+        map.end()
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct Withdrawal {
+    #[serde(flatten)]
+    quantity: HashMap<Unit, f64>,
+    to: Option<Unit>,
+}
+
+impl Withdrawal {
+    pub fn from_fields(fields: HashMap<String, String>) -> Result<Withdrawal, String> {
+        let mut quantity = HashMap::new();
+        let mut to = None;
+
+        for (key, value) in fields {
+            if key == "to" {
+                to = Some(unit(value.trim())?);
+                continue;
+            }
+
+            let value = value
+                .trim()
+                .parse()
+                .map_err(|_| format!("{key} is not a number"))?;
+            quantity.insert(unit(&key)?, value);
+        }
+
+        Ok(Withdrawal { quantity, to })
+    }
+
+    pub fn convert(self) -> Result<Quantity, String> {
+        if self.quantity.len() != 1 {
+            return Err(format!(
+                "expected exactly one unit, got {}",
+                self.quantity.len()
+            ));
+        }
+
+        let (unit, value) = self.quantity.into_iter().next().unwrap();
+
+        if !value.is_finite() {
+            return Err("quantity must be a finite number".to_string());
+        }
+
+        if value < 0.0 {
+            return Err("quantity must not be negative".to_string());
+        }
+
+        Ok(Quantity { unit, value }.convert(self.to))
+    }
+}
+
+fn unit(name: &str) -> Result<Unit, String> {
+    serde_json::from_value(serde_json::Value::String(name.to_string()))
+        .map_err(|_| format!("unknown unit {name}"))
+}
+
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+pub enum Request {
+    Single(Withdrawal),
+    Batch(Vec<Withdrawal>),
+}
+
+#[derive(serde::Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Outcome {
+    Withdrawn { quantity: Quantity },
+    Invalid { error: String },
+    NoMilk,
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    const UNITS: &[Unit] = &[
+        Unit::Ml,
+        Unit::Cl,
+        Unit::Liters,
+        Unit::Litres,
+        Unit::FlOz,
+        Unit::ImperialFlOz,
+        Unit::Cups,
+        Unit::UsPints,
+        Unit::Pints,
+        Unit::Quarts,
+        Unit::ImperialQuarts,
+        Unit::Gallons,
+        Unit::ImperialGallons,
+    ];
+
+    fn withdrawal(fields: &[(&str, &str)]) -> Result<Quantity, String> {
+        let fields = fields
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+
+        Withdrawal::from_fields(fields)?.convert()
+    }
+
+    proptest! {
+        #[test]
+        fn round_trips_every_pair(
+            from in proptest::sample::select(UNITS),
+            to in proptest::sample::select(UNITS),
+            value in 0.0..1e12_f64,
+        ) {
+            let back = Quantity { unit: from, value }
+                .convert(Some(to))
+                .convert(Some(from));
+
+            prop_assert!(back.unit == from);
+            prop_assert!(
+                (back.value - value).abs() <= value * 1e-12,
+                "{} did not survive a round trip",
+                value
+            );
+        }
+
+        #[test]
+        fn rounds_to_finite_values(value in 0.0..f64::MAX, decimals: u32) {
+            let quantity = Quantity { unit: Unit::Liters, value }.round(Some(decimals));
+
+            prop_assert!(quantity.value.is_finite());
+        }
+    }
+
+    #[test]
+    fn rounds_to_decimals() {
+        let round = |value, decimals| {
+            Quantity {
+                unit: Unit::Liters,
+                value,
+            }
+            .round(decimals)
+            .value
+        };
+
+        assert_eq!(round(1.23456, None), 1.23456);
+        assert_eq!(round(1.23456, Some(2)), 1.23);
+        assert_eq!(round(1.23456, Some(0)), 1.0);
+        assert_eq!(round(1.23456, Some(400)), 1.23456);
+        assert_eq!(round(1e300, Some(2)), 1e300);
+    }
+
+    #[test]
+    fn converts_to_paired_unit() {
+        let quantity = Quantity {
+            unit: Unit::Gallons,
+            value: 1.0,
+        }
+        .convert(None);
+        assert!(quantity.unit == Unit::Liters);
+        assert!((quantity.value - 3.785_411_784).abs() < 1e-12);
+
+        let quantity = Quantity {
+            unit: Unit::Litres,
+            value: 0.568_261_25,
+        }
+        .convert(None);
+        assert!(quantity.unit == Unit::Pints);
+        assert!((quantity.value - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn rejects_negative() {
+        assert!(withdrawal(&[("liters", "-1")]).is_err());
+        assert!(withdrawal(&[("liters", "-0.0001")]).is_err());
+        assert!(withdrawal(&[("liters", "0")]).is_ok());
+    }
+
+    #[test]
+    fn rejects_non_finite() {
+        assert!(withdrawal(&[("liters", "NaN")]).is_err());
+        assert!(withdrawal(&[("gallons", "inf")]).is_err());
+        assert!(withdrawal(&[("gallons", "-inf")]).is_err());
+    }
+
+    #[test]
+    fn rejects_other_than_one_unit() {
+        assert!(withdrawal(&[]).is_err());
+        assert!(withdrawal(&[("to", "ml")]).is_err());
+        assert!(withdrawal(&[("liters", "1"), ("gallons", "1")]).is_err());
+
+        let json = serde_json::from_str::<Withdrawal>(r#"{"liters": 1, "pints": 2}"#).unwrap();
+        assert!(json.convert().is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_units() {
+        assert!(withdrawal(&[("barrels", "1")]).is_err());
+        assert!(withdrawal(&[("liters", "1"), ("to", "barrels")]).is_err());
+        assert!(withdrawal(&[("liters", "one")]).is_err());
+    }
+}
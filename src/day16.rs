@@ -1,81 +1,751 @@
+mod inspect;
+mod jwe;
+mod keys;
+mod policy;
+mod refresh;
+
+use std::{collections::HashMap, sync::LazyLock, time::Duration};
+
 use actix_web::{cookie, get, http, post, web, HttpRequest, HttpResponse};
+use shuttle_runtime::tokio::{self, sync::Mutex};
 
-const SECRET: &[u8] = b"penguin";
+static STATE: LazyLock<web::Data<State>> = LazyLock::new(Default::default);
+
+#[derive(Default)]
+struct State {
+    revoked: Mutex<HashMap<uuid::Uuid, chrono::DateTime<chrono::Utc>>>,
+}
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
+    let state = STATE.clone();
+
     cfg.service(post_wrap)
         .service(get_unwrap)
-        .service(post_decode);
+        .service(post_revoke)
+        .service(refresh::post_refresh)
+        .service(post_decode)
+        .service(inspect::post_inspect)
+        .service(get_jwks)
+        .app_data(state);
+}
+
+pub async fn prune_revocations(pool: sqlx::PgPool) {
+    let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+
+    loop {
+        interval.tick().await;
+
+        let now = chrono::Utc::now();
+
+        STATE.revoked.lock().await.retain(|_, exp| *exp > now);
+
+        if let Err(e) = sqlx::query!("DELETE FROM revoked_gifts WHERE expires_at <= $1", now)
+            .execute(&pool)
+            .await
+        {
+            tracing::error!("failed to prune revoked gifts: {e}");
+        }
+
+        if let Err(e) = sqlx::query!(
+            "DELETE FROM gift_refresh_families WHERE expires_at <= $1",
+            now
+        )
+        .execute(&pool)
+        .await
+        {
+            tracing::error!("failed to prune refresh token families: {e}");
+        }
+    }
+}
+
+pub struct Config {
+    issuer: String,
+    default_ttl: u64,
+    max_ttl: u64,
+    max_nbf: u64,
+    refresh_ttl: u64,
+    signer: keys::Signer,
+    jwks: keys::KeySet,
+    encrypter: jwe::Encrypter,
+    decode_policy: policy::DecodePolicy,
+    cookie_secure: bool,
+    cookie_http_only: bool,
+    cookie_same_site: cookie::SameSite,
+    cookie_path: String,
+}
+
+impl Config {
+    pub fn from_secrets(secrets: &shuttle_runtime::SecretStore) -> Self {
+        let issuer = secrets
+            .get("DAY16_ISSUER")
+            .unwrap_or_else(|| "shuttlings-cch24".to_string());
+        let default_ttl = secrets
+            .get("DAY16_GIFT_TTL")
+            .and_then(|t| t.parse().ok())
+            .unwrap_or(60 * 60 * 24);
+        let max_ttl = secrets
+            .get("DAY16_GIFT_MAX_TTL")
+            .and_then(|t| t.parse().ok())
+            .unwrap_or(60 * 60 * 24 * 7);
+        let max_nbf = secrets
+            .get("DAY16_GIFT_MAX_NBF")
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(60 * 60 * 24 * 30);
+        let refresh_ttl = secrets
+            .get("DAY16_REFRESH_TTL")
+            .and_then(|t| t.parse().ok())
+            .unwrap_or(60 * 60 * 24 * 30);
+
+        let signer = keys::Signer::new(
+            secrets.get("DAY16_SIGNING_KEY").as_deref(),
+            secrets
+                .get("DAY16_SIGNING_KID")
+                .unwrap_or_else(|| "gift".to_string()),
+        );
+
+        let source = match (
+            secrets.get("DAY16_JWKS_URL"),
+            secrets.get("DAY16_JWKS_FILE"),
+        ) {
+            (Some(url), _) => Some(keys::Source::Url(url)),
+            (_, Some(path)) => Some(keys::Source::File(path)),
+            _ => None,
+        };
+        let refresh = secrets
+            .get("DAY16_JWKS_REFRESH")
+            .and_then(|r| r.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(Duration::from_secs(5 * 60));
+        let jwks = keys::KeySet::new(source, refresh);
+
+        let encrypter = jwe::Encrypter::new(secrets.get("DAY16_ENCRYPTION_KEY").as_deref());
+
+        let decode_policy = policy::DecodePolicy::from_secrets(secrets);
+
+        let cookie_secure = secrets
+            .get("DAY16_COOKIE_SECURE")
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(true);
+        let cookie_http_only = secrets
+            .get("DAY16_COOKIE_HTTP_ONLY")
+            .and_then(|h| h.parse().ok())
+            .unwrap_or(true);
+        let cookie_same_site = match secrets.get("DAY16_COOKIE_SAME_SITE").as_deref() {
+            Some("lax" | "Lax") => cookie::SameSite::Lax,
+            Some("none" | "None") => cookie::SameSite::None,
+            _ => cookie::SameSite::Strict,
+        };
+        let cookie_path = secrets
+            .get("DAY16_COOKIE_PATH")
+            .unwrap_or_else(|| "/16".to_string());
+
+        Self {
+            issuer,
+            default_ttl,
+            max_ttl,
+            max_nbf,
+            refresh_ttl,
+            signer,
+            jwks,
+            encrypter,
+            decode_policy,
+            cookie_secure,
+            cookie_http_only,
+            cookie_same_site,
+            cookie_path,
+        }
+    }
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
 struct Claims {
     payload: serde_json::Value,
     exp: usize,
+    iat: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nbf: Option<usize>,
+    iss: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    aud: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sub: Option<String>,
+    jti: uuid::Uuid,
+}
+
+#[derive(serde::Deserialize)]
+struct WrapInfo {
+    ttl: Option<u64>,
+    nbf: Option<usize>,
+    aud: Option<String>,
+    sub: Option<String>,
+    #[serde(default)]
+    encrypt: bool,
+    #[serde(default)]
+    transport: Transport,
+    #[serde(default)]
+    refresh: bool,
+}
+
+#[derive(Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Transport {
+    #[default]
+    Cookie,
+    Body,
+}
+
+#[derive(serde::Serialize)]
+struct Gift {
+    token: String,
+    token_type: &'static str,
+    expires_in: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refresh_token: Option<String>,
 }
 
 #[post("/16/wrap")]
-async fn post_wrap(payload: web::Json<serde_json::Value>) -> HttpResponse {
+async fn post_wrap(
+    web::Query(WrapInfo {
+        ttl,
+        nbf,
+        aud,
+        sub,
+        encrypt,
+        transport,
+        refresh,
+    }): web::Query<WrapInfo>,
+    config: web::Data<Config>,
+    pool: web::Data<sqlx::PgPool>,
+    payload: web::Json<serde_json::Value>,
+) -> HttpResponse {
     let payload = payload.into_inner();
-    let exp = (chrono::Utc::now() + chrono::Days::new(1)).timestamp() as usize;
+    let iat = chrono::Utc::now().timestamp() as usize;
+    let ttl = ttl.unwrap_or(config.default_ttl).min(config.max_ttl) as usize;
+
+    // Gifts may only be postponed so far, which also keeps `exp` within the
+    // range of a timestamp.
+    if nbf.is_some_and(|nbf| nbf > iat.saturating_add(config.max_nbf as usize)) {
+        return HttpResponse::BadRequest().finish();
+    }
+    let Some(exp) = nbf.unwrap_or(iat).max(iat).checked_add(ttl) else {
+        return HttpResponse::BadRequest().finish();
+    };
+
+    let claims = Claims {
+        payload,
+        exp,
+        iat,
+        nbf,
+        iss: config.issuer.clone(),
+        aud,
+        sub,
+        jti: uuid::Uuid::new_v4(),
+    };
 
-    let claims = Claims { payload, exp };
+    let refresh_token = if refresh {
+        Some(refresh::issue(&claims, ttl, encrypt, &config, &pool).await)
+    } else {
+        None
+    };
+
+    let token = sign_gift(&claims, encrypt, &config);
+
+    gift_response(token, exp - iat, refresh_token, transport, &config)
+}
+
+fn sign_gift(claims: &Claims, encrypt: bool, config: &Config) -> String {
     let token = jsonwebtoken::encode(
-        &jsonwebtoken::Header::default(),
-        &claims,
-        &jsonwebtoken::EncodingKey::from_secret(SECRET),
+        &config.signer.header(),
+        claims,
+        config.signer.encoding_key(),
     )
     .unwrap();
 
+    if encrypt {
+        config.encrypter.encrypt(&token)
+    } else {
+        token
+    }
+}
+
+fn gift_response(
+    token: String,
+    expires_in: usize,
+    refresh_token: Option<String>,
+    transport: Transport,
+    config: &Config,
+) -> HttpResponse {
+    if let Transport::Body = transport {
+        let gift = Gift {
+            token,
+            token_type: "Bearer",
+            expires_in,
+            refresh_token,
+        };
+
+        return HttpResponse::Ok().json(gift);
+    }
+
     let cookie = cookie::CookieBuilder::new("gift", token)
+        .http_only(config.cookie_http_only)
+        .secure(config.cookie_secure)
+        .same_site(config.cookie_same_site)
+        .path(config.cookie_path.clone())
+        .max_age(cookie::time::Duration::seconds(expires_in as i64))
         .finish()
         .to_string();
 
-    HttpResponse::Ok()
-        .insert_header((http::header::SET_COOKIE, cookie))
-        .finish()
+    let mut response = HttpResponse::Ok();
+    response.append_header((http::header::SET_COOKIE, cookie));
+
+    if let Some(refresh_token) = refresh_token {
+        let cookie = cookie::CookieBuilder::new("gift_refresh", refresh_token)
+            .http_only(true)
+            .secure(config.cookie_secure)
+            .same_site(config.cookie_same_site)
+            .path(format!(
+                "{}/refresh",
+                config.cookie_path.trim_end_matches('/')
+            ))
+            .max_age(cookie::time::Duration::seconds(config.refresh_ttl as i64))
+            .finish()
+            .to_string();
+
+        response.append_header((http::header::SET_COOKIE, cookie));
+    }
+
+    response.finish()
+}
+
+#[derive(serde::Deserialize)]
+struct UnwrapInfo {
+    aud: Option<String>,
+    #[serde(default)]
+    verbose: bool,
 }
 
 #[get("/16/unwrap")]
-async fn get_unwrap(req: HttpRequest) -> HttpResponse {
-    let Some(token) = req.cookie("gift") else {
+async fn get_unwrap(
+    req: HttpRequest,
+    web::Query(UnwrapInfo { aud, verbose }): web::Query<UnwrapInfo>,
+    config: web::Data<Config>,
+    state: web::Data<State>,
+    pool: web::Data<sqlx::PgPool>,
+) -> HttpResponse {
+    let Some(token) = gift_token(&req) else {
+        return HttpResponse::BadRequest().finish();
+    };
+
+    let mut validation = config.signer.validation();
+    validation.validate_nbf = true;
+    validation.set_issuer(&[&config.issuer]);
+    if let Some(aud) = aud {
+        validation.set_audience(&[aud]);
+    }
+
+    let claims = match decode_gift(&token, &config, &validation) {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+
+    if is_revoked(claims.jti, &state, &pool).await {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    if verbose {
+        HttpResponse::Ok().json(claims)
+    } else {
+        HttpResponse::Ok().json(claims.payload)
+    }
+}
+
+fn gift_token(req: &HttpRequest) -> Option<String> {
+    let bearer = req
+        .headers()
+        .get(http::header::AUTHORIZATION)
+        .and_then(|a| a.to_str().ok())
+        .and_then(|a| a.strip_prefix("Bearer "));
+
+    match bearer {
+        Some(token) => Some(token.to_string()),
+        None => req.cookie("gift").map(|c| c.value().to_string()),
+    }
+}
+
+pub fn gift_subject(req: &HttpRequest) -> Option<String> {
+    let config = req.app_data::<web::Data<Config>>()?;
+    let token = gift_token(req)?;
+
+    let mut validation = config.signer.validation();
+    validation.validate_aud = false;
+    validation.set_issuer(&[&config.issuer]);
+
+    decode_gift(&token, config, &validation).ok()?.sub
+}
+
+fn decode_gift(
+    token: &str,
+    config: &Config,
+    validation: &jsonwebtoken::Validation,
+) -> Result<Claims, HttpResponse> {
+    let token = if jwe::Encrypter::is_encrypted(token) {
+        let Some(jwt) = config.encrypter.decrypt(token) else {
+            return Err(HttpResponse::Unauthorized().finish());
+        };
+
+        jwt
+    } else {
+        token.to_string()
+    };
+
+    jsonwebtoken::decode::<Claims>(&token, config.signer.decoding_key(), validation)
+        .map(|token_data| token_data.claims)
+        .map_err(error_response)
+}
+
+async fn is_revoked(jti: uuid::Uuid, state: &State, pool: &sqlx::PgPool) -> bool {
+    if state.revoked.lock().await.contains_key(&jti) {
+        return true;
+    }
+
+    let Some(revoked) = sqlx::query!("SELECT expires_at FROM revoked_gifts WHERE jti = $1", jti)
+        .fetch_optional(pool)
+        .await
+        .unwrap()
+    else {
+        return false;
+    };
+
+    state.revoked.lock().await.insert(jti, revoked.expires_at);
+
+    true
+}
+
+#[post("/16/revoke")]
+async fn post_revoke(
+    req: HttpRequest,
+    config: web::Data<Config>,
+    state: web::Data<State>,
+    pool: web::Data<sqlx::PgPool>,
+) -> HttpResponse {
+    let Some(token) = gift_token(&req) else {
         return HttpResponse::BadRequest().finish();
     };
 
-    let claims = jsonwebtoken::decode::<Claims>(
-        token.value(),
-        &jsonwebtoken::DecodingKey::from_secret(SECRET),
-        &jsonwebtoken::Validation::default(),
+    let mut validation = config.signer.validation();
+    validation.validate_exp = false;
+    validation.validate_aud = false;
+    validation.set_issuer(&[&config.issuer]);
+
+    let claims = match decode_gift(&token, &config, &validation) {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+
+    let expires_at = chrono::DateTime::from_timestamp(claims.exp as i64, 0).unwrap();
+
+    sqlx::query!(
+        "INSERT INTO revoked_gifts(jti, expires_at) VALUES($1, $2) ON CONFLICT DO NOTHING",
+        claims.jti,
+        expires_at
     )
-    .unwrap()
-    .claims;
+    .execute(pool.as_ref())
+    .await
+    .unwrap();
 
-    HttpResponse::Ok().json(claims.payload)
+    state.revoked.lock().await.insert(claims.jti, expires_at);
+    refresh::revoke_gift(claims.jti, &state, &pool).await;
+
+    HttpResponse::Ok().finish()
+}
+
+fn error_response(e: jsonwebtoken::errors::Error) -> HttpResponse {
+    match e.kind() {
+        jsonwebtoken::errors::ErrorKind::InvalidSignature => HttpResponse::Unauthorized().finish(),
+        _ => HttpResponse::BadRequest().finish(),
+    }
 }
 
 #[post("/16/decode")]
-async fn post_decode(jwt: String) -> HttpResponse {
-    let mut validation = jsonwebtoken::Validation::default();
-    validation.algorithms = vec![
-        jsonwebtoken::Algorithm::RS256,
-        jsonwebtoken::Algorithm::RS512,
-    ];
-    validation.set_required_spec_claims::<String>(&[]);
-
-    let claims = match jsonwebtoken::decode::<serde_json::Value>(
-        &jwt,
-        &jsonwebtoken::DecodingKey::from_rsa_pem(include_bytes!("../day16_santa_public_key.pem"))
-            .unwrap(),
-        &validation,
-    ) {
+async fn post_decode(jwt: String, config: web::Data<Config>) -> HttpResponse {
+    let header = match jsonwebtoken::decode_header(&jwt) {
+        Ok(header) => header,
+        Err(e) => return error_response(e),
+    };
+
+    if !config.decode_policy.accepts(header.alg) {
+        return HttpResponse::BadRequest().finish();
+    }
+
+    let Some(key) = decoding_key(&header, &config).await else {
+        return HttpResponse::Unauthorized().finish();
+    };
+
+    let validation = config.decode_policy.validation(header.alg);
+
+    let claims = match jsonwebtoken::decode::<serde_json::Value>(&jwt, &key, &validation) {
         Ok(token_data) => token_data.claims,
-        Err(e) => match e.kind() {
-            jsonwebtoken::errors::ErrorKind::InvalidSignature => {
-                return HttpResponse::Unauthorized().finish()
-            }
-            _ => return HttpResponse::BadRequest().finish(),
-        },
+        Err(e) => return error_response(e),
     };
 
     HttpResponse::Ok().json(claims)
 }
+
+// Keys from the JWKS take precedence; tokens whose kid is not found there
+// are still checked against Santa's public key.
+async fn decoding_key(
+    header: &jsonwebtoken::Header,
+    config: &Config,
+) -> Option<jsonwebtoken::DecodingKey> {
+    if let Some(kid) = &header.kid {
+        if let Some(key) = config.jwks.find(kid, header.alg).await {
+            return Some(key);
+        }
+    }
+
+    policy::is_rsa(header.alg)
+        .then(|| jsonwebtoken::DecodingKey::from_rsa_pem(keys::SANTA_PUBLIC_KEY).unwrap())
+}
+
+#[get("/.well-known/jwks.json")]
+async fn get_jwks(config: web::Data<Config>) -> HttpResponse {
+    HttpResponse::Ok().json(config.signer.jwks())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::collections::BTreeMap;
+
+    use jsonwebtoken::{Algorithm, Header};
+    use shuttle_runtime::SecretStore;
+
+    use super::*;
+
+    pub fn config(secrets: &[(&str, &str)]) -> Config {
+        let secrets = secrets
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string().into()))
+            .collect::<BTreeMap<_, _>>();
+
+        Config::from_secrets(&SecretStore::new(secrets))
+    }
+
+    pub fn gift(config: &Config, sub: &str) -> String {
+        let iat = chrono::Utc::now().timestamp() as usize;
+        let claims = Claims {
+            payload: serde_json::json!({}),
+            exp: iat + 60,
+            iat,
+            nbf: None,
+            iss: config.issuer.clone(),
+            aud: None,
+            sub: Some(sub.to_string()),
+            jti: uuid::Uuid::new_v4(),
+        };
+
+        sign_gift(&claims, false, config)
+    }
+
+    async fn wrap(config: Config, query: &str) -> actix_web::dev::ServiceResponse {
+        let pool = sqlx::PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(pool))
+                .service(post_wrap),
+        )
+        .await;
+
+        let req = actix_web::test::TestRequest::post()
+            .uri(&format!("/16/wrap?{query}"))
+            .set_json(serde_json::json!({ "gift": "socks" }))
+            .to_request();
+
+        actix_web::test::call_service(&app, req).await
+    }
+
+    fn jwks_file(jwks: &jsonwebtoken::jwk::JwkSet) -> String {
+        let path = std::env::temp_dir().join(format!("jwks-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&path, serde_json::to_string(jwks).unwrap()).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    fn unsigned(header: &serde_json::Value) -> String {
+        use base64::Engine;
+
+        let encode = |v: &serde_json::Value| {
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(v.to_string())
+        };
+
+        format!("{}.{}.", encode(header), encode(&claims()))
+    }
+
+    async fn decode(config: Config, jwt: String) -> actix_web::http::StatusCode {
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(web::Data::new(config))
+                .service(post_decode),
+        )
+        .await;
+
+        let req = actix_web::test::TestRequest::post()
+            .uri("/16/decode")
+            .set_payload(jwt)
+            .to_request();
+
+        actix_web::test::call_service(&app, req).await.status()
+    }
+
+    fn claims() -> serde_json::Value {
+        serde_json::json!({ "exp": chrono::Utc::now().timestamp() + 60 })
+    }
+
+    fn header(alg: Algorithm, kid: Option<&str>) -> Header {
+        Header {
+            kid: kid.map(str::to_string),
+            ..Header::new(alg)
+        }
+    }
+
+    #[actix_web::test]
+    async fn postpones_gifts_by_nbf() {
+        let nbf = chrono::Utc::now().timestamp() + 60 * 60;
+        let res = wrap(
+            config(&[("DAY16_GIFT_TTL", "60")]),
+            &format!("nbf={nbf}&transport=body"),
+        )
+        .await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::OK);
+
+        let gift: serde_json::Value = actix_web::test::read_body_json(res).await;
+        let expires_in = gift["expires_in"].as_i64().unwrap();
+        assert!((60 * 60..=60 * 60 + 60 + 1).contains(&expires_in));
+    }
+
+    #[actix_web::test]
+    async fn rejects_nbf_too_far_ahead() {
+        let nbf = chrono::Utc::now().timestamp() + 60 * 60;
+
+        for nbf in [nbf.to_string(), u64::MAX.to_string()] {
+            let res = wrap(
+                config(&[("DAY16_GIFT_MAX_NBF", "60")]),
+                &format!("nbf={nbf}"),
+            )
+            .await;
+            assert_eq!(res.status(), actix_web::http::StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs DATABASE_URL"]
+    async fn unwraps_encrypted_gifts(pool: sqlx::PgPool) {
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(web::Data::new(config(&[])))
+                .app_data(web::Data::new(State::default()))
+                .app_data(web::Data::new(pool))
+                .service(post_wrap)
+                .service(get_unwrap),
+        )
+        .await;
+
+        let req = actix_web::test::TestRequest::post()
+            .uri("/16/wrap?encrypt=true&transport=body")
+            .set_json(serde_json::json!({ "gift": "socks" }))
+            .to_request();
+        let gift: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+        let token = gift["token"].as_str().unwrap();
+        assert!(jwe::Encrypter::is_encrypted(token));
+
+        let req = actix_web::test::TestRequest::get()
+            .uri("/16/unwrap")
+            .insert_header((http::header::AUTHORIZATION, format!("Bearer {token}")))
+            .to_request();
+        let payload: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+
+        assert_eq!(payload, serde_json::json!({ "gift": "socks" }));
+    }
+
+    #[actix_web::test]
+    async fn falls_back_to_santa_without_jwks() {
+        let config = config(&[]);
+
+        assert!(
+            decoding_key(&header(Algorithm::RS256, Some("santa-1")), &config)
+                .await
+                .is_some()
+        );
+        assert!(decoding_key(&header(Algorithm::RS256, None), &config)
+            .await
+            .is_some());
+        assert!(
+            decoding_key(&header(Algorithm::ES256, Some("santa-1")), &config)
+                .await
+                .is_none()
+        );
+    }
+
+    #[actix_web::test]
+    async fn falls_back_to_santa_for_unknown_kid() {
+        let config = config(&[("DAY16_JWKS_FILE", "/nonexistent/jwks.json")]);
+
+        assert!(
+            decoding_key(&header(Algorithm::RS256, Some("santa-1")), &config)
+                .await
+                .is_some()
+        );
+    }
+
+    #[actix_web::test]
+    async fn decodes_tokens_signed_by_a_jwks_key() {
+        let signer = keys::tests::signer("elf-1");
+        let path = jwks_file(&signer.jwks());
+        let jwt = jsonwebtoken::encode(&signer.header(), &claims(), signer.encoding_key()).unwrap();
+
+        let status = decode(config(&[("DAY16_JWKS_FILE", &path)]), jwt).await;
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(status, actix_web::http::StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn rejects_hmac_signed_with_public_key() {
+        let key = jsonwebtoken::EncodingKey::from_secret(keys::SANTA_PUBLIC_KEY);
+        let jwt = jsonwebtoken::encode(&Header::new(Algorithm::HS256), &claims(), &key).unwrap();
+
+        assert_eq!(
+            decode(config(&[]), jwt.clone()).await,
+            actix_web::http::StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            decode(config(&[("DAY16_DECODE_ALGORITHMS", "HS256,RS256")]), jwt).await,
+            actix_web::http::StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[actix_web::test]
+    async fn rejects_alg_none() {
+        let jwt = unsigned(&serde_json::json!({ "alg": "none", "typ": "JWT" }));
+
+        assert_eq!(
+            decode(config(&[]), jwt).await,
+            actix_web::http::StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[actix_web::test]
+    async fn rejects_jwk_of_another_key_type() {
+        let signer = keys::tests::signer("elf-1");
+        let path = jwks_file(&signer.jwks());
+        let config = config(&[
+            ("DAY16_JWKS_FILE", &path),
+            ("DAY16_DECODE_ALGORITHMS", "RS256,ES256"),
+        ]);
+
+        let jwt = unsigned(&serde_json::json!({ "alg": "ES256", "typ": "JWT", "kid": "elf-1" }));
+        let status = decode(config, format!("{jwt}AAAA")).await;
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(status, actix_web::http::StatusCode::UNAUTHORIZED);
+    }
+}
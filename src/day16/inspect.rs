@@ -0,0 +1,151 @@
+use actix_web::{post, web, HttpResponse};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+
+use super::{jwe, keys, policy, Config};
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Expiry {
+    Valid,
+    Expired,
+    NotYetValid,
+    Missing,
+}
+
+#[derive(Default, serde::Serialize)]
+struct Inspection {
+    encrypted: bool,
+    header: Option<jsonwebtoken::Header>,
+    claims: Option<serde_json::Value>,
+    algorithm: Option<Algorithm>,
+    kid: Option<String>,
+    expiry: Option<Expiry>,
+    verified_by: Option<String>,
+    errors: Vec<String>,
+}
+
+#[post("/16/inspect")]
+pub async fn post_inspect(jwt: String, config: web::Data<Config>) -> HttpResponse {
+    let mut inspection = Inspection::default();
+
+    let jwt = jwt.trim();
+
+    if jwe::Encrypter::is_encrypted(jwt) {
+        inspection.encrypted = true;
+        inspection
+            .errors
+            .push("encrypted tokens cannot be inspected".to_string());
+        return HttpResponse::Ok().json(inspection);
+    }
+
+    let header = match jsonwebtoken::decode_header(jwt) {
+        Ok(header) => header,
+        Err(e) => {
+            inspection.errors.push(format!("malformed header: {e}"));
+            return HttpResponse::Ok().json(inspection);
+        }
+    };
+
+    inspection.algorithm = Some(header.alg);
+    inspection.kid = header.kid.clone();
+
+    if !config.decode_policy.accepts(header.alg) {
+        inspection
+            .errors
+            .push(format!("algorithm {:?} is not accepted", header.alg));
+    }
+
+    match jwt
+        .split('.')
+        .nth(1)
+        .and_then(|c| URL_SAFE_NO_PAD.decode(c).ok())
+        .map(|c| serde_json::from_slice::<serde_json::Value>(&c))
+    {
+        Some(Ok(claims)) => {
+            inspection.expiry = Some(expiry(&claims, config.decode_policy.leeway()));
+            match inspection.expiry {
+                Some(Expiry::Expired) => inspection.errors.push("token has expired".to_string()),
+                Some(Expiry::NotYetValid) => {
+                    inspection.errors.push("token is not yet valid".to_string())
+                }
+                _ => {}
+            }
+
+            inspection.claims = Some(claims);
+        }
+        Some(Err(e)) => inspection.errors.push(format!("malformed claims: {e}")),
+        None => inspection
+            .errors
+            .push("claims are not valid base64".to_string()),
+    }
+
+    match verified_by(jwt, &header, &config).await {
+        Some((name, key)) => {
+            let mut validation = config.decode_policy.validation(header.alg);
+            validation.validate_exp = false;
+
+            if let Err(e) = jsonwebtoken::decode::<serde_json::Value>(jwt, &key, &validation) {
+                match e.kind() {
+                    jsonwebtoken::errors::ErrorKind::ImmatureSignature => {}
+                    _ => inspection.errors.push(format!("invalid claims: {e}")),
+                }
+            }
+
+            inspection.verified_by = Some(name);
+        }
+        None => inspection
+            .errors
+            .push("signature does not match any configured key".to_string()),
+    }
+
+    inspection.header = Some(header);
+
+    HttpResponse::Ok().json(inspection)
+}
+
+fn expiry(claims: &serde_json::Value, leeway: u64) -> Expiry {
+    let now = jsonwebtoken::get_current_timestamp();
+
+    if claims["nbf"].as_u64().is_some_and(|nbf| nbf > now + leeway) {
+        return Expiry::NotYetValid;
+    }
+
+    match claims["exp"].as_u64() {
+        Some(exp) if exp + leeway < now => Expiry::Expired,
+        Some(_) => Expiry::Valid,
+        None => Expiry::Missing,
+    }
+}
+
+async fn verified_by(
+    jwt: &str,
+    header: &jsonwebtoken::Header,
+    config: &Config,
+) -> Option<(String, DecodingKey)> {
+    let mut candidates = vec![];
+
+    if policy::is_rsa(header.alg) {
+        candidates.push((
+            "santa".to_string(),
+            DecodingKey::from_rsa_pem(keys::SANTA_PUBLIC_KEY).unwrap(),
+        ));
+    }
+
+    if let Some(kid) = &header.kid {
+        if let Some(key) = config.jwks.find(kid, header.alg).await {
+            candidates.push((format!("jwks:{kid}"), key));
+        }
+    }
+
+    candidates.push(("gift".to_string(), config.signer.decoding_key().clone()));
+
+    let mut validation = Validation::new(header.alg);
+    validation.validate_exp = false;
+    validation.validate_aud = false;
+    validation.set_required_spec_claims::<String>(&[]);
+
+    candidates
+        .into_iter()
+        .find(|(_, key)| jsonwebtoken::decode::<serde_json::Value>(jwt, key, &validation).is_ok())
+}
@@ -0,0 +1,260 @@
+use std::time::{Duration, Instant};
+
+use base64::Engine;
+use jsonwebtoken::{jwk, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use rsa::{pkcs1::DecodeRsaPrivateKey, pkcs8::DecodePrivateKey, traits::PublicKeyParts};
+use shuttle_runtime::tokio::{fs, sync::RwLock};
+
+pub const SECRET: &[u8] = b"penguin";
+
+pub const SANTA_PUBLIC_KEY: &[u8] = include_bytes!("../../day16_santa_public_key.pem");
+
+pub struct Signer {
+    header: Header,
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    jwk: Option<jwk::Jwk>,
+}
+
+impl Signer {
+    pub fn new(pem: Option<&str>, kid: String) -> Self {
+        let Some(pem) = pem else {
+            return Self {
+                header: Header::default(),
+                encoding_key: EncodingKey::from_secret(SECRET),
+                decoding_key: DecodingKey::from_secret(SECRET),
+                jwk: None,
+            };
+        };
+
+        let private_key = rsa::RsaPrivateKey::from_pkcs8_pem(pem)
+            .or_else(|_| rsa::RsaPrivateKey::from_pkcs1_pem(pem))
+            .unwrap();
+        let public_key = private_key.to_public_key();
+
+        let n =
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(public_key.n().to_bytes_be());
+        let e =
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(public_key.e().to_bytes_be());
+
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(kid.clone());
+
+        let jwk = jwk::Jwk {
+            common: jwk::CommonParameters {
+                public_key_use: Some(jwk::PublicKeyUse::Signature),
+                key_algorithm: Some(jwk::KeyAlgorithm::RS256),
+                key_id: Some(kid),
+                ..Default::default()
+            },
+            algorithm: jwk::AlgorithmParameters::RSA(jwk::RSAKeyParameters {
+                key_type: jwk::RSAKeyType::RSA,
+                n,
+                e,
+            }),
+        };
+
+        Self {
+            header,
+            encoding_key: EncodingKey::from_rsa_pem(pem.as_bytes()).unwrap(),
+            decoding_key: DecodingKey::from_jwk(&jwk).unwrap(),
+            jwk: Some(jwk),
+        }
+    }
+
+    pub fn header(&self) -> Header {
+        self.header.clone()
+    }
+
+    pub fn encoding_key(&self) -> &EncodingKey {
+        &self.encoding_key
+    }
+
+    pub fn decoding_key(&self) -> &DecodingKey {
+        &self.decoding_key
+    }
+
+    pub fn validation(&self) -> Validation {
+        Validation::new(self.header.alg)
+    }
+
+    pub fn jwks(&self) -> jwk::JwkSet {
+        jwk::JwkSet {
+            keys: self.jwk.iter().cloned().collect(),
+        }
+    }
+}
+
+pub enum Source {
+    File(String),
+    Url(String),
+}
+
+pub struct KeySet {
+    source: Option<Source>,
+    refresh: Duration,
+    cache: RwLock<Option<(jwk::JwkSet, Instant)>>,
+}
+
+impl KeySet {
+    pub fn new(source: Option<Source>, refresh: Duration) -> Self {
+        Self {
+            source,
+            refresh,
+            cache: RwLock::new(None),
+        }
+    }
+
+    pub async fn find(&self, kid: &str, alg: Algorithm) -> Option<DecodingKey> {
+        let fresh = matches!(
+            &*self.cache.read().await,
+            Some((_, fetched_at)) if fetched_at.elapsed() < self.refresh
+        );
+
+        if !fresh {
+            if let Some(jwks) = self.load().await {
+                *self.cache.write().await = Some((jwks, Instant::now()));
+            }
+        }
+
+        let cache = self.cache.read().await;
+        let (jwks, _) = cache.as_ref()?;
+
+        let jwk = jwks.find(kid)?;
+        if !super::policy::key_matches(jwk, alg) {
+            return None;
+        }
+
+        DecodingKey::from_jwk(jwk).ok()
+    }
+
+    async fn load(&self) -> Option<jwk::JwkSet> {
+        let jwks = match self.source.as_ref()? {
+            Source::File(path) => fs::read_to_string(path)
+                .await
+                .map_err(|e| e.to_string())
+                .and_then(|s| serde_json::from_str(&s).map_err(|e| e.to_string())),
+            Source::Url(url) => match reqwest::get(url).await {
+                Ok(response) => response.json().await.map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            },
+        };
+
+        jwks.inspect_err(|e| tracing::error!("failed to load jwks: {e}"))
+            .ok()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, LazyLock,
+    };
+
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use rsa::pkcs8::{EncodePrivateKey, LineEnding};
+
+    use super::*;
+
+    pub static PEM: LazyLock<String> = LazyLock::new(|| {
+        rsa::RsaPrivateKey::new(&mut rand::thread_rng(), 2048)
+            .unwrap()
+            .to_pkcs8_pem(LineEnding::LF)
+            .unwrap()
+            .to_string()
+    });
+
+    pub fn signer(kid: &str) -> Signer {
+        Signer::new(Some(&PEM), kid.to_string())
+    }
+
+    async fn serve(jwks: jwk::JwkSet) -> (String, Arc<AtomicUsize>) {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let jwks = web::Data::new(jwks);
+
+        let counter = hits.clone();
+        let server = HttpServer::new(move || {
+            let counter = counter.clone();
+
+            App::new().app_data(jwks.clone()).route(
+                "/jwks.json",
+                web::get().to(move |jwks: web::Data<jwk::JwkSet>| {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    async move { HttpResponse::Ok().json(jwks.as_ref()) }
+                }),
+            )
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+
+        let url = format!("http://{}/jwks.json", server.addrs()[0]);
+        actix_web::rt::spawn(server.run());
+
+        (url, hits)
+    }
+
+    #[actix_web::test]
+    async fn finds_keys_served_by_url() {
+        let (url, _) = serve(signer("santa-1").jwks()).await;
+        let keys = KeySet::new(Some(Source::Url(url)), Duration::from_secs(60));
+
+        assert!(keys.find("santa-1", Algorithm::RS256).await.is_some());
+        assert!(keys.find("santa-2", Algorithm::RS256).await.is_none());
+    }
+
+    #[actix_web::test]
+    async fn rejects_keys_of_another_type() {
+        let (url, _) = serve(signer("santa-1").jwks()).await;
+        let keys = KeySet::new(Some(Source::Url(url)), Duration::from_secs(60));
+
+        assert!(keys.find("santa-1", Algorithm::ES256).await.is_none());
+        assert!(keys.find("santa-1", Algorithm::HS256).await.is_none());
+        assert!(keys.find("santa-1", Algorithm::RS512).await.is_none());
+    }
+
+    #[actix_web::test]
+    async fn caches_until_refresh() {
+        let (url, hits) = serve(signer("santa-1").jwks()).await;
+
+        let cached = KeySet::new(Some(Source::Url(url.clone())), Duration::from_secs(60));
+        cached.find("santa-1", Algorithm::RS256).await;
+        cached.find("santa-1", Algorithm::RS256).await;
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        let refreshed = KeySet::new(Some(Source::Url(url)), Duration::ZERO);
+        refreshed.find("santa-1", Algorithm::RS256).await;
+        refreshed.find("santa-1", Algorithm::RS256).await;
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[actix_web::test]
+    async fn unreachable_url_finds_nothing() {
+        let keys = KeySet::new(
+            Some(Source::Url("http://127.0.0.1:1/jwks.json".to_string())),
+            Duration::from_secs(60),
+        );
+
+        assert!(keys.find("santa-1", Algorithm::RS256).await.is_none());
+    }
+
+    #[actix_web::test]
+    async fn finds_keys_in_file() {
+        let path = std::env::temp_dir().join(format!("jwks-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            serde_json::to_string(&signer("santa-1").jwks()).unwrap(),
+        )
+        .unwrap();
+
+        let keys = KeySet::new(
+            Some(Source::File(path.to_string_lossy().to_string())),
+            Duration::from_secs(60),
+        );
+        let found = keys.find("santa-1", Algorithm::RS256).await;
+        std::fs::remove_file(path).unwrap();
+
+        assert!(found.is_some());
+    }
+}
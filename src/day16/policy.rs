@@ -0,0 +1,261 @@
+use std::str::FromStr;
+
+use jsonwebtoken::{jwk, Algorithm, Validation};
+
+pub struct DecodePolicy {
+    algorithms: Vec<Algorithm>,
+    required_claims: Vec<String>,
+    leeway: u64,
+    issuer: Vec<String>,
+    audience: Vec<String>,
+}
+
+impl DecodePolicy {
+    pub fn from_secrets(secrets: &shuttle_runtime::SecretStore) -> Self {
+        let list = |key: &str| -> Vec<String> {
+            secrets
+                .get(key)
+                .map(|l| {
+                    l.split(',')
+                        .map(|i| i.trim().to_string())
+                        .filter(|i| !i.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+
+        let mut algorithms = list("DAY16_DECODE_ALGORITHMS")
+            .iter()
+            .filter_map(|a| match Algorithm::from_str(a) {
+                Ok(a) if is_hmac(a) => {
+                    tracing::warn!("ignoring symmetric algorithm {a:?} for /16/decode");
+                    None
+                }
+                Ok(a) => Some(a),
+                Err(_) => {
+                    tracing::warn!("ignoring unknown algorithm {a} for /16/decode");
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        if algorithms.is_empty() {
+            algorithms = vec![Algorithm::RS256, Algorithm::RS512];
+        }
+
+        let leeway = secrets
+            .get("DAY16_DECODE_LEEWAY")
+            .and_then(|l| l.parse().ok())
+            .unwrap_or(60);
+
+        Self {
+            algorithms,
+            required_claims: list("DAY16_DECODE_REQUIRED_CLAIMS"),
+            leeway,
+            issuer: list("DAY16_DECODE_ISSUER"),
+            audience: list("DAY16_DECODE_AUDIENCE"),
+        }
+    }
+
+    pub fn accepts(&self, alg: Algorithm) -> bool {
+        !is_hmac(alg) && self.algorithms.contains(&alg)
+    }
+
+    pub fn leeway(&self) -> u64 {
+        self.leeway
+    }
+
+    pub fn validation(&self, alg: Algorithm) -> Validation {
+        let mut validation = Validation::new(alg);
+        validation.leeway = self.leeway;
+        validation.set_required_spec_claims(&self.required_claims);
+
+        if !self.issuer.is_empty() {
+            validation.set_issuer(&self.issuer);
+        }
+
+        if self.audience.is_empty() {
+            validation.validate_aud = false;
+        } else {
+            validation.set_audience(&self.audience);
+        }
+
+        validation
+    }
+}
+
+fn is_hmac(alg: Algorithm) -> bool {
+    matches!(alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512)
+}
+
+pub fn is_rsa(alg: Algorithm) -> bool {
+    matches!(
+        alg,
+        Algorithm::RS256
+            | Algorithm::RS384
+            | Algorithm::RS512
+            | Algorithm::PS256
+            | Algorithm::PS384
+            | Algorithm::PS512
+    )
+}
+
+pub fn key_matches(jwk: &jwk::Jwk, alg: Algorithm) -> bool {
+    if jwk
+        .common
+        .key_algorithm
+        .is_some_and(|k| k.to_string() != format!("{alg:?}"))
+    {
+        return false;
+    }
+
+    match &jwk.algorithm {
+        jwk::AlgorithmParameters::RSA(_) => is_rsa(alg),
+        jwk::AlgorithmParameters::EllipticCurve(ec) => matches!(
+            (&ec.curve, alg),
+            (jwk::EllipticCurve::P256, Algorithm::ES256)
+                | (jwk::EllipticCurve::P384, Algorithm::ES384)
+        ),
+        jwk::AlgorithmParameters::OctetKeyPair(okp) => {
+            okp.curve == jwk::EllipticCurve::Ed25519 && alg == Algorithm::EdDSA
+        }
+        jwk::AlgorithmParameters::OctetKey(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use shuttle_runtime::SecretStore;
+
+    use super::*;
+
+    fn policy(algorithms: &str) -> DecodePolicy {
+        let secrets = BTreeMap::from([(
+            "DAY16_DECODE_ALGORITHMS".to_string(),
+            algorithms.to_string().into(),
+        )]);
+
+        DecodePolicy::from_secrets(&SecretStore::new(secrets))
+    }
+
+    fn jwk(
+        key_algorithm: Option<jwk::KeyAlgorithm>,
+        algorithm: jwk::AlgorithmParameters,
+    ) -> jwk::Jwk {
+        jwk::Jwk {
+            common: jwk::CommonParameters {
+                key_algorithm,
+                ..Default::default()
+            },
+            algorithm,
+        }
+    }
+
+    fn rsa() -> jwk::AlgorithmParameters {
+        jwk::AlgorithmParameters::RSA(jwk::RSAKeyParameters {
+            key_type: jwk::RSAKeyType::RSA,
+            n: "AQAB".to_string(),
+            e: "AQAB".to_string(),
+        })
+    }
+
+    fn ec(curve: jwk::EllipticCurve) -> jwk::AlgorithmParameters {
+        jwk::AlgorithmParameters::EllipticCurve(jwk::EllipticCurveKeyParameters {
+            key_type: jwk::EllipticCurveKeyType::EC,
+            curve,
+            x: "AQAB".to_string(),
+            y: "AQAB".to_string(),
+        })
+    }
+
+    #[test]
+    fn accepts_rsa_by_default() {
+        let policy = policy("");
+
+        assert!(policy.accepts(Algorithm::RS256));
+        assert!(policy.accepts(Algorithm::RS512));
+        assert!(!policy.accepts(Algorithm::RS384));
+        assert!(!policy.accepts(Algorithm::ES256));
+        assert!(!policy.accepts(Algorithm::HS256));
+    }
+
+    #[test]
+    fn accepts_configured_algorithms() {
+        let policy = policy("ES256, PS256, bogus");
+
+        assert!(policy.accepts(Algorithm::ES256));
+        assert!(policy.accepts(Algorithm::PS256));
+        assert!(!policy.accepts(Algorithm::RS256));
+    }
+
+    #[test]
+    fn never_accepts_hmac() {
+        let policy = policy("HS256,HS384,HS512,RS256");
+
+        assert!(!policy.accepts(Algorithm::HS256));
+        assert!(!policy.accepts(Algorithm::HS384));
+        assert!(!policy.accepts(Algorithm::HS512));
+        assert!(policy.accepts(Algorithm::RS256));
+    }
+
+    #[test]
+    fn matches_rsa_keys() {
+        let key = jwk(None, rsa());
+
+        assert!(key_matches(&key, Algorithm::RS256));
+        assert!(key_matches(&key, Algorithm::PS512));
+        assert!(!key_matches(&key, Algorithm::ES256));
+        assert!(!key_matches(&key, Algorithm::HS256));
+        assert!(!key_matches(&key, Algorithm::EdDSA));
+    }
+
+    #[test]
+    fn matches_declared_key_algorithm() {
+        let key = jwk(Some(jwk::KeyAlgorithm::RS256), rsa());
+
+        assert!(key_matches(&key, Algorithm::RS256));
+        assert!(!key_matches(&key, Algorithm::RS512));
+    }
+
+    #[test]
+    fn matches_curves() {
+        assert!(key_matches(
+            &jwk(None, ec(jwk::EllipticCurve::P256)),
+            Algorithm::ES256
+        ));
+        assert!(!key_matches(
+            &jwk(None, ec(jwk::EllipticCurve::P256)),
+            Algorithm::ES384
+        ));
+        assert!(key_matches(
+            &jwk(None, ec(jwk::EllipticCurve::P384)),
+            Algorithm::ES384
+        ));
+        assert!(!key_matches(
+            &jwk(None, ec(jwk::EllipticCurve::P256)),
+            Algorithm::RS256
+        ));
+
+        let ed25519 = jwk::AlgorithmParameters::OctetKeyPair(jwk::OctetKeyPairParameters {
+            key_type: jwk::OctetKeyPairType::OctetKeyPair,
+            curve: jwk::EllipticCurve::Ed25519,
+            x: "AQAB".to_string(),
+        });
+        assert!(key_matches(&jwk(None, ed25519), Algorithm::EdDSA));
+    }
+
+    #[test]
+    fn never_matches_symmetric_keys() {
+        let key = jwk(
+            None,
+            jwk::AlgorithmParameters::OctetKey(jwk::OctetKeyParameters {
+                key_type: jwk::OctetKeyType::Octet,
+                value: "AQAB".to_string(),
+            }),
+        );
+
+        assert!(!key_matches(&key, Algorithm::HS256));
+        assert!(!key_matches(&key, Algorithm::RS256));
+    }
+}
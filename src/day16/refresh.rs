@@ -0,0 +1,326 @@
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use rand::{distributions::Alphanumeric, Rng};
+use sha2::Digest;
+
+use super::{gift_response, sign_gift, Claims, Config, State, Transport};
+
+#[derive(serde::Deserialize)]
+struct RefreshInfo {
+    refresh_token: String,
+}
+
+fn new_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
+fn hash(token: &str) -> String {
+    format!("{:x}", sha2::Sha256::digest(token))
+}
+
+fn timestamp(timestamp: usize) -> chrono::DateTime<chrono::Utc> {
+    chrono::DateTime::from_timestamp(timestamp as i64, 0).unwrap()
+}
+
+pub async fn issue(
+    claims: &Claims,
+    ttl: usize,
+    encrypt: bool,
+    config: &Config,
+    pool: &sqlx::PgPool,
+) -> String {
+    let family = uuid::Uuid::new_v4();
+    let expires_at = chrono::Utc::now() + chrono::Duration::seconds(config.refresh_ttl as i64);
+
+    sqlx::query!(
+        "INSERT INTO gift_refresh_families(family, payload, aud, sub, ttl, encrypted, expires_at) VALUES($1, $2, $3, $4, $5, $6, $7)",
+        family,
+        claims.payload.to_string(),
+        claims.aud,
+        claims.sub,
+        ttl as i64,
+        encrypt,
+        expires_at
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+
+    rotate(family, claims, pool).await
+}
+
+async fn rotate(family: uuid::Uuid, claims: &Claims, pool: &sqlx::PgPool) -> String {
+    let token = new_token();
+
+    sqlx::query!(
+        "INSERT INTO gift_refresh_tokens(token_hash, family, gift_jti, gift_expires_at) VALUES($1, $2, $3, $4)",
+        hash(&token),
+        family,
+        claims.jti,
+        timestamp(claims.exp)
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+
+    token
+}
+
+async fn revoke_family(family: uuid::Uuid, state: &State, pool: &sqlx::PgPool) {
+    sqlx::query!(
+        "UPDATE gift_refresh_families SET revoked = true WHERE family = $1",
+        family
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+
+    let revoked = sqlx::query!(
+        "INSERT INTO revoked_gifts(jti, expires_at) SELECT gift_jti, gift_expires_at FROM gift_refresh_tokens WHERE family = $1 ON CONFLICT DO NOTHING RETURNING jti, expires_at",
+        family
+    )
+    .fetch_all(pool)
+    .await
+    .unwrap();
+
+    state
+        .revoked
+        .lock()
+        .await
+        .extend(revoked.into_iter().map(|r| (r.jti, r.expires_at)));
+}
+
+// Revoking a gift also ends the refresh family it was issued from, so it
+// cannot simply be refreshed into a new one.
+pub async fn revoke_gift(jti: uuid::Uuid, state: &State, pool: &sqlx::PgPool) {
+    let Some(issued) = sqlx::query!(
+        "SELECT family FROM gift_refresh_tokens WHERE gift_jti = $1 LIMIT 1",
+        jti
+    )
+    .fetch_optional(pool)
+    .await
+    .unwrap() else {
+        return;
+    };
+
+    revoke_family(issued.family, state, pool).await;
+}
+
+#[post("/16/refresh")]
+pub async fn post_refresh(
+    req: HttpRequest,
+    info: Option<web::Json<RefreshInfo>>,
+    config: web::Data<Config>,
+    state: web::Data<State>,
+    pool: web::Data<sqlx::PgPool>,
+) -> HttpResponse {
+    let (token, transport) = match (info, req.cookie("gift_refresh")) {
+        (Some(info), _) => (info.into_inner().refresh_token, Transport::Body),
+        (None, Some(cookie)) => (cookie.value().to_string(), Transport::Cookie),
+        (None, None) => return HttpResponse::BadRequest().finish(),
+    };
+    let token_hash = hash(&token);
+
+    let Some(rotated) = sqlx::query!(
+        "UPDATE gift_refresh_tokens SET used = true WHERE token_hash = $1 AND NOT used RETURNING family",
+        token_hash
+    )
+    .fetch_optional(pool.as_ref())
+    .await
+    .unwrap() else {
+        if let Some(reused) = sqlx::query!(
+            "SELECT family FROM gift_refresh_tokens WHERE token_hash = $1",
+            token_hash
+        )
+        .fetch_optional(pool.as_ref())
+        .await
+        .unwrap()
+        {
+            tracing::warn!("refresh token reuse detected for family {}", reused.family);
+            revoke_family(reused.family, &state, &pool).await;
+        }
+
+        return HttpResponse::Unauthorized().finish();
+    };
+
+    let family = sqlx::query!(
+        "SELECT payload, aud, sub, ttl, encrypted, revoked, expires_at FROM gift_refresh_families WHERE family = $1",
+        rotated.family
+    )
+    .fetch_one(pool.as_ref())
+    .await
+    .unwrap();
+
+    if family.revoked || family.expires_at <= chrono::Utc::now() {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    let iat = chrono::Utc::now().timestamp() as usize;
+    let ttl = family.ttl as usize;
+
+    let claims = Claims {
+        payload: serde_json::from_str(&family.payload).unwrap(),
+        exp: iat + ttl,
+        iat,
+        nbf: None,
+        iss: config.issuer.clone(),
+        aud: family.aud,
+        sub: family.sub,
+        jti: uuid::Uuid::new_v4(),
+    };
+
+    let refresh_token = rotate(rotated.family, &claims, &pool).await;
+    let token = sign_gift(&claims, family.encrypted, &config);
+
+    gift_response(token, ttl, Some(refresh_token), transport, &config)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::StatusCode, test, App};
+
+    use super::*;
+    use crate::day16::{get_unwrap, post_revoke, post_wrap};
+
+    struct Gift {
+        token: String,
+        refresh_token: String,
+    }
+
+    impl Gift {
+        fn from_json(gift: serde_json::Value) -> Self {
+            Self {
+                token: gift["token"].as_str().unwrap().to_string(),
+                refresh_token: gift["refresh_token"].as_str().unwrap().to_string(),
+            }
+        }
+    }
+
+    fn wrap() -> test::TestRequest {
+        test::TestRequest::post()
+            .uri("/16/wrap?refresh=true&transport=body")
+            .set_json(serde_json::json!({ "gift": "socks" }))
+    }
+
+    fn refresh(refresh_token: &str) -> test::TestRequest {
+        test::TestRequest::post()
+            .uri("/16/refresh")
+            .set_json(serde_json::json!({ "refresh_token": refresh_token }))
+    }
+
+    fn bearer(req: test::TestRequest, token: &str) -> test::TestRequest {
+        req.insert_header((
+            actix_web::http::header::AUTHORIZATION,
+            format!("Bearer {token}"),
+        ))
+    }
+
+    fn unwrap(token: &str) -> test::TestRequest {
+        bearer(test::TestRequest::get().uri("/16/unwrap"), token)
+    }
+
+    fn revoke(token: &str) -> test::TestRequest {
+        bearer(test::TestRequest::post().uri("/16/revoke"), token)
+    }
+
+    macro_rules! app {
+        ($pool:expr) => {
+            test::init_service(
+                App::new()
+                    .app_data(web::Data::new(crate::day16::tests::config(&[])))
+                    .app_data(web::Data::new(State::default()))
+                    .app_data(web::Data::new($pool))
+                    .service(post_wrap)
+                    .service(get_unwrap)
+                    .service(post_revoke)
+                    .service(post_refresh),
+            )
+            .await
+        };
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs DATABASE_URL"]
+    async fn rotates_refresh_tokens(pool: sqlx::PgPool) {
+        let app = app!(pool);
+
+        let first = Gift::from_json(test::call_and_read_body_json(&app, wrap().to_request()).await);
+        let second = Gift::from_json(
+            test::call_and_read_body_json(&app, refresh(&first.refresh_token).to_request()).await,
+        );
+        assert_ne!(first.refresh_token, second.refresh_token);
+
+        let payload: serde_json::Value =
+            test::call_and_read_body_json(&app, unwrap(&second.token).to_request()).await;
+        assert_eq!(payload, serde_json::json!({ "gift": "socks" }));
+
+        let res = test::call_service(&app, refresh(&second.refresh_token).to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs DATABASE_URL"]
+    async fn reuse_revokes_the_family(pool: sqlx::PgPool) {
+        let app = app!(pool);
+
+        let first = Gift::from_json(test::call_and_read_body_json(&app, wrap().to_request()).await);
+        let second = Gift::from_json(
+            test::call_and_read_body_json(&app, refresh(&first.refresh_token).to_request()).await,
+        );
+
+        let res = test::call_service(&app, refresh(&first.refresh_token).to_request()).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let res = test::call_service(&app, refresh(&second.refresh_token).to_request()).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        for token in [&first.token, &second.token] {
+            let res = test::call_service(&app, unwrap(token).to_request()).await;
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs DATABASE_URL"]
+    async fn revoking_a_gift_revokes_the_family(pool: sqlx::PgPool) {
+        let app = app!(pool);
+
+        let first = Gift::from_json(test::call_and_read_body_json(&app, wrap().to_request()).await);
+        let second = Gift::from_json(
+            test::call_and_read_body_json(&app, refresh(&first.refresh_token).to_request()).await,
+        );
+
+        let res = test::call_service(&app, revoke(&second.token).to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        for token in [&first.token, &second.token] {
+            let res = test::call_service(&app, unwrap(token).to_request()).await;
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        }
+
+        let res = test::call_service(&app, refresh(&second.refresh_token).to_request()).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs DATABASE_URL"]
+    async fn revoking_a_gift_without_refresh_tokens(pool: sqlx::PgPool) {
+        let app = app!(pool);
+
+        let req = test::TestRequest::post()
+            .uri("/16/wrap?transport=body")
+            .set_json(serde_json::json!({ "gift": "socks" }))
+            .to_request();
+        let gift: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let token = gift["token"].as_str().unwrap();
+
+        let res = test::call_service(&app, revoke(token).to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = test::call_service(&app, unwrap(token).to_request()).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+}
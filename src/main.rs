@@ -5,28 +5,77 @@ mod day09;
 mod day12;
 mod day16;
 mod day19;
+mod rate_limit;
 
-use actix_web::{error, web, HttpResponse};
+use std::time::Duration;
+
+use actix_web::{error, http, web, HttpResponse};
 use shuttle_actix_web::ShuttleActixWeb;
 
 #[shuttle_runtime::main]
 async fn main(
     #[shuttle_shared_db::Postgres] pool: sqlx::PgPool,
+    #[shuttle_runtime::Secrets] secrets: shuttle_runtime::SecretStore,
 ) -> ShuttleActixWeb<impl FnOnce(&mut web::ServiceConfig) + Send + Clone + 'static> {
     sqlx::migrate!().run(&pool).await.unwrap();
 
+    shuttle_runtime::tokio::spawn(day16::prune_revocations(pool.clone()));
+
+    let day05 = web::Data::new(day05::Config::from_secrets(&secrets));
+    let day09 = web::Data::new(day09::State::from_secrets(&secrets, pool.clone()).await);
+    let day16 = web::Data::new(day16::Config::from_secrets(&secrets));
+
+    let rate_limit = rate_limit::RateLimit::new(rate_limit::client::Keys::from_secrets(&secrets))
+        .route(
+            http::Method::POST,
+            "/19/draft",
+            rate_limit::bucket::Policy {
+                capacity: 10,
+                refill: 1,
+                interval: Duration::from_secs(6),
+                cost: 1,
+            },
+        )
+        .route(
+            http::Method::POST,
+            "/16/wrap",
+            rate_limit::bucket::Policy {
+                capacity: 20,
+                refill: 1,
+                interval: Duration::from_secs(3),
+                cost: 1,
+            },
+        )
+        .route(
+            http::Method::POST,
+            "/12/place/",
+            rate_limit::bucket::Policy {
+                capacity: 100,
+                refill: 10,
+                interval: Duration::from_secs(1),
+                cost: 1,
+            },
+        );
+
     let config = move |cfg: &mut web::ServiceConfig| {
-        cfg.configure(day00::configure)
-            .configure(day02::configure)
-            .configure(day05::configure)
-            .configure(day09::configure)
-            .configure(day12::configure)
-            .configure(day16::configure)
-            .configure(day19::configure)
-            .app_data(web::Data::new(pool))
-            .app_data(web::PathConfig::default().error_handler(|err, _| {
-                error::InternalError::from_response(err, HttpResponse::BadRequest().into()).into()
-            }));
+        cfg.service(
+            web::scope("")
+                .wrap(rate_limit)
+                .configure(day00::configure)
+                .configure(day02::configure)
+                .configure(day05::configure)
+                .configure(day09::configure)
+                .configure(day12::configure)
+                .configure(day16::configure)
+                .configure(day19::configure),
+        )
+        .app_data(web::Data::new(pool))
+        .app_data(day05)
+        .app_data(day09)
+        .app_data(day16)
+        .app_data(web::PathConfig::default().error_handler(|err, _| {
+            error::InternalError::from_response(err, HttpResponse::BadRequest().into()).into()
+        }));
     };
 
     Ok(config.into())
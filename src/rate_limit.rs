@@ -0,0 +1,220 @@
+pub mod bucket;
+pub mod client;
+
+use std::{future::Future, pin::Pin, rc::Rc, sync::Arc, time::Duration};
+
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http, Error, HttpResponse, HttpResponseBuilder,
+};
+
+struct Rule {
+    method: http::Method,
+    path: String,
+    backend: Box<dyn bucket::Backend>,
+}
+
+impl Rule {
+    fn matches(&self, req: &ServiceRequest) -> bool {
+        req.method() == self.method && is_prefix(&self.path, req.path())
+    }
+}
+
+// Compares whole segments so `/16/wrap` covers `/16/wrap/...` but not
+// `/16/wrapper`.
+fn is_prefix(prefix: &str, path: &str) -> bool {
+    let mut segments = path.split('/').filter(|s| !s.is_empty());
+
+    prefix
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .all(|s| segments.next() == Some(s))
+}
+
+pub fn rate_limited<'a>(
+    response: &'a mut HttpResponseBuilder,
+    status: &bucket::Status,
+) -> &'a mut HttpResponseBuilder {
+    for header in status.headers() {
+        response.insert_header(header);
+    }
+
+    response
+}
+
+pub fn too_many_requests(status: &bucket::Status, permits: usize) -> HttpResponseBuilder {
+    let mut response = HttpResponse::TooManyRequests();
+    rate_limited(&mut response, status).insert_header((
+        http::header::RETRY_AFTER,
+        status.retry_after(permits).as_secs_f64().ceil().to_string(),
+    ));
+
+    response
+}
+
+struct Inner {
+    keys: client::Keys,
+    rules: Vec<Rule>,
+}
+
+#[derive(Clone)]
+pub struct RateLimit {
+    inner: Arc<Inner>,
+}
+
+impl RateLimit {
+    pub fn new(keys: client::Keys) -> Self {
+        let inner = Arc::new(Inner {
+            keys,
+            rules: Vec::new(),
+        });
+
+        Self { inner }
+    }
+
+    pub fn route(mut self, method: http::Method, path: &str, policy: bucket::Policy) -> Self {
+        let backend = bucket::Memory::new(policy, 10_000, Duration::from_secs(10 * 60));
+
+        Arc::get_mut(&mut self.inner)
+            .expect("routes must be added before the middleware is shared")
+            .rules
+            .push(Rule {
+                method,
+                path: path.to_string(),
+                backend: Box::new(backend),
+            });
+
+        self
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(RateLimitMiddleware {
+            service: Rc::new(service),
+            inner: self.inner.clone(),
+        }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+    inner: Arc<Inner>,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let inner = self.inner.clone();
+
+        Box::pin(async move {
+            let Some(rule) = inner.rules.iter().find(|r| r.matches(&req)) else {
+                return service
+                    .call(req)
+                    .await
+                    .map(ServiceResponse::map_into_left_body);
+            };
+
+            let key = inner.keys.key(req.request());
+            let cost = rule.backend.policy().cost;
+            let status = rule.backend.try_acquire(&key, cost).await;
+
+            if !status.acquired {
+                let response = too_many_requests(&status, cost).finish();
+                return Ok(req.into_response(response).map_into_right_body());
+            }
+
+            let mut res = service.call(req).await?;
+            for (name, value) in status.headers() {
+                res.headers_mut().insert(
+                    http::header::HeaderName::from_static(name),
+                    http::header::HeaderValue::from_str(&value).unwrap(),
+                );
+            }
+
+            Ok(res.map_into_left_body())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use actix_web::{web, App};
+
+    use super::*;
+
+    #[test]
+    fn matches_whole_segments() {
+        assert!(is_prefix("/16/wrap", "/16/wrap"));
+        assert!(is_prefix("/16/wrap", "/16/wrap/"));
+        assert!(is_prefix("/12/place/", "/12/place/cookie/1"));
+        assert!(!is_prefix("/16/wrap", "/16/wrapper"));
+        assert!(!is_prefix("/16/wrap", "/16"));
+        assert!(!is_prefix("/12/place/", "/12/placement"));
+    }
+
+    #[actix_web::test]
+    async fn limits_only_matching_routes() {
+        let keys = client::Keys::from_secrets(&shuttle_runtime::SecretStore::new(BTreeMap::new()));
+        let rate_limit = RateLimit::new(keys).route(
+            http::Method::POST,
+            "/16/wrap",
+            bucket::Policy {
+                capacity: 1,
+                refill: 1,
+                interval: Duration::from_secs(60 * 60),
+                cost: 1,
+            },
+        );
+
+        let app = actix_web::test::init_service(
+            App::new()
+                .wrap(rate_limit)
+                .route("/16/wrap", web::post().to(HttpResponse::Ok))
+                .route("/16/wrapper", web::post().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let post = |uri| actix_web::test::TestRequest::post().uri(uri).to_request();
+
+        let res = actix_web::test::call_service(&app, post("/16/wrap")).await;
+        assert_eq!(res.status(), http::StatusCode::OK);
+        assert_eq!(res.headers().get("ratelimit-remaining").unwrap(), "0");
+
+        let res = actix_web::test::call_service(&app, post("/16/wrap")).await;
+        assert_eq!(res.status(), http::StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(
+            res.headers().get(http::header::RETRY_AFTER).unwrap(),
+            "3600"
+        );
+
+        for _ in 0..3 {
+            let res = actix_web::test::call_service(&app, post("/16/wrapper")).await;
+            assert_eq!(res.status(), http::StatusCode::OK);
+            assert!(!res.headers().contains_key("ratelimit-remaining"));
+        }
+    }
+}
@@ -0,0 +1,867 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use shuttle_runtime::tokio::{
+    self,
+    sync::{Mutex, Notify},
+};
+
+#[derive(Clone, Copy)]
+pub struct Policy {
+    pub capacity: usize,
+    pub refill: usize,
+    pub interval: Duration,
+    pub cost: usize,
+}
+
+pub struct Status {
+    pub acquired: bool,
+    pub limit: usize,
+    pub remaining: usize,
+    pub next_refill: Duration,
+    policy: Policy,
+}
+
+impl Status {
+    fn refills_until(&self, tokens: usize) -> u32 {
+        tokens
+            .saturating_sub(self.remaining)
+            .div_ceil(self.policy.refill) as u32
+    }
+
+    pub fn reset(&self) -> Duration {
+        match self.refills_until(self.limit) {
+            0 => Duration::ZERO,
+            n => self.next_refill + self.policy.interval * (n - 1),
+        }
+    }
+
+    pub fn retry_after(&self, permits: usize) -> Duration {
+        match self.refills_until(permits) {
+            0 => Duration::ZERO,
+            n => self.next_refill + self.policy.interval * (n - 1),
+        }
+    }
+
+    pub fn headers(&self) -> [(&'static str, String); 3] {
+        [
+            ("ratelimit-limit", self.limit.to_string()),
+            ("ratelimit-remaining", self.remaining.to_string()),
+            (
+                "ratelimit-reset",
+                self.reset().as_secs_f64().ceil().to_string(),
+            ),
+        ]
+    }
+}
+
+// Adds the refills of every whole interval in `elapsed`, returning the new
+// balance and how much of `elapsed` those refills account for. A full bucket
+// accounts for all of it, so refilling starts over once tokens are taken.
+fn refilled(policy: &Policy, tokens: usize, elapsed: Duration) -> (usize, Duration) {
+    let interval = policy.interval.as_nanos().max(1);
+    let intervals = elapsed.as_nanos() / interval;
+
+    let added = usize::try_from(intervals)
+        .unwrap_or(usize::MAX)
+        .saturating_mul(policy.refill);
+    let tokens = tokens.saturating_add(added).min(policy.capacity);
+
+    if tokens == policy.capacity {
+        return (tokens, elapsed);
+    }
+
+    let partial = u64::try_from(elapsed.as_nanos() % interval).unwrap_or(u64::MAX);
+    (
+        tokens,
+        elapsed.saturating_sub(Duration::from_nanos(partial)),
+    )
+}
+
+#[async_trait::async_trait]
+pub trait Backend: Send + Sync {
+    fn policy(&self) -> Policy;
+
+    async fn set_policy(&self, policy: Policy);
+
+    async fn try_acquire(&self, key: &str, permits: usize) -> Status;
+
+    async fn acquire(&self, key: &str, permits: usize, timeout: Duration) -> Status;
+
+    async fn status(&self, key: &str) -> Status;
+
+    async fn refill(&self, key: &str, amount: Option<usize>);
+}
+
+// Tokens are settled lazily whenever the bucket is used. Waiters queue on a
+// fair mutex so they are served in arrival order, and are woken early when
+// the bucket is refilled or its policy changes.
+struct Bucket {
+    tokens: std::sync::Mutex<(usize, Instant)>,
+    queue: Mutex<()>,
+    refilled: Notify,
+}
+
+impl Bucket {
+    fn new(tokens: usize) -> Self {
+        Self {
+            tokens: std::sync::Mutex::new((tokens, Instant::now())),
+            queue: Mutex::new(()),
+            refilled: Notify::new(),
+        }
+    }
+
+    fn update(&self, policy: &Policy, f: impl FnOnce(&mut usize) -> bool) -> Status {
+        let mut state = self.tokens.lock().unwrap();
+        let (tokens, refilled_at) = &mut *state;
+        let now = Instant::now();
+
+        let (settled, refilled) = refilled(policy, *tokens, now - *refilled_at);
+        *tokens = settled;
+        *refilled_at += refilled;
+
+        let acquired = f(tokens);
+        let next_refill = if *tokens < policy.capacity {
+            policy.interval.saturating_sub(now - *refilled_at)
+        } else {
+            Duration::ZERO
+        };
+
+        Status {
+            acquired,
+            limit: policy.capacity,
+            remaining: *tokens,
+            next_refill,
+            policy: *policy,
+        }
+    }
+
+    fn take(&self, policy: &Policy, permits: usize) -> Status {
+        self.update(policy, |tokens| {
+            let acquired = *tokens >= permits;
+            if acquired {
+                *tokens -= permits;
+            }
+
+            acquired
+        })
+    }
+}
+
+// Keys are also indexed by last use so idle and least recently used buckets
+// are evicted from the front instead of by scanning every bucket.
+#[derive(Default)]
+struct Buckets {
+    buckets: HashMap<String, (Arc<Bucket>, Instant)>,
+    by_use: BTreeSet<(Instant, String)>,
+}
+
+impl Buckets {
+    fn get(&self, key: &str) -> Option<&Arc<Bucket>> {
+        self.buckets.get(key).map(|(bucket, _)| bucket)
+    }
+
+    fn touch(&mut self, key: &str) -> Option<&Arc<Bucket>> {
+        let (bucket, last_used) = self.buckets.get_mut(key)?;
+
+        self.by_use.remove(&(*last_used, key.to_string()));
+        *last_used = Instant::now();
+        self.by_use.insert((*last_used, key.to_string()));
+
+        Some(bucket)
+    }
+
+    fn insert(&mut self, key: &str, bucket: Bucket) -> &Arc<Bucket> {
+        let last_used = Instant::now();
+        self.by_use.insert((last_used, key.to_string()));
+        self.buckets
+            .insert(key.to_string(), (Arc::new(bucket), last_used));
+
+        &self.buckets[key].0
+    }
+
+    fn len(&self) -> usize {
+        self.buckets.len()
+    }
+
+    fn values(&self) -> impl Iterator<Item = &Arc<Bucket>> {
+        self.buckets.values().map(|(bucket, _)| bucket)
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some((_, last_used)) = self.buckets.remove(key) {
+            self.by_use.remove(&(last_used, key.to_string()));
+        }
+    }
+
+    fn oldest(&self) -> Option<(Instant, String)> {
+        self.by_use.first().cloned()
+    }
+}
+
+pub struct Memory {
+    policy: RwLock<Policy>,
+    buckets: Mutex<Buckets>,
+    max_buckets: usize,
+    idle: Duration,
+}
+
+impl Memory {
+    pub fn new(policy: Policy, max_buckets: usize, idle: Duration) -> Self {
+        Self {
+            policy: RwLock::new(policy),
+            buckets: Mutex::new(Buckets::default()),
+            max_buckets,
+            idle,
+        }
+    }
+
+    async fn bucket(&self, key: &str) -> Arc<Bucket> {
+        let mut buckets = self.buckets.lock().await;
+
+        if let Some(bucket) = buckets.touch(key) {
+            return bucket.clone();
+        }
+
+        self.evict(&mut buckets);
+
+        buckets
+            .insert(key, Bucket::new(self.policy().capacity))
+            .clone()
+    }
+
+    fn evict(&self, buckets: &mut Buckets) {
+        while let Some((last_used, key)) = buckets.oldest() {
+            if last_used.elapsed() < self.idle && buckets.len() < self.max_buckets {
+                break;
+            }
+
+            buckets.remove(&key);
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Backend for Memory {
+    fn policy(&self) -> Policy {
+        *self.policy.read().unwrap()
+    }
+
+    // Tokens accrued under the old policy are settled before it is replaced.
+    async fn set_policy(&self, policy: Policy) {
+        let buckets = self.buckets.lock().await;
+        let old = self.policy();
+
+        for bucket in buckets.values() {
+            bucket.update(&old, |tokens| {
+                *tokens = (*tokens).min(policy.capacity);
+                false
+            });
+        }
+
+        *self.policy.write().unwrap() = policy;
+
+        for bucket in buckets.values() {
+            bucket.refilled.notify_waiters();
+        }
+    }
+
+    async fn try_acquire(&self, key: &str, permits: usize) -> Status {
+        let bucket = self.bucket(key).await;
+
+        // Queued waiters come first.
+        let status = match bucket.queue.try_lock() {
+            Ok(_queue) => bucket.take(&self.policy(), permits),
+            Err(_) => bucket.update(&self.policy(), |_| false),
+        };
+
+        status
+    }
+
+    async fn acquire(&self, key: &str, permits: usize, timeout: Duration) -> Status {
+        let deadline = tokio::time::Instant::now() + timeout;
+        let bucket = self.bucket(key).await;
+
+        let Ok(_queue) = tokio::time::timeout_at(deadline, bucket.queue.lock()).await else {
+            return bucket.update(&self.policy(), |_| false);
+        };
+
+        loop {
+            let refilled = bucket.refilled.notified();
+            tokio::pin!(refilled);
+            refilled.as_mut().enable();
+
+            let status = bucket.take(&self.policy(), permits);
+            if status.acquired || tokio::time::Instant::now() >= deadline {
+                return status;
+            }
+
+            let retry_at = tokio::time::Instant::now() + status.retry_after(permits);
+
+            tokio::select! {
+                _ = tokio::time::sleep_until(retry_at.min(deadline)) => {}
+                _ = refilled => {}
+            }
+        }
+    }
+
+    async fn status(&self, key: &str) -> Status {
+        let bucket = self.buckets.lock().await.get(key).cloned();
+        let bucket = bucket.unwrap_or_else(|| Arc::new(Bucket::new(self.policy().capacity)));
+
+        bucket.update(&self.policy(), |_| false)
+    }
+
+    async fn refill(&self, key: &str, amount: Option<usize>) {
+        let Some(bucket) = self.buckets.lock().await.get(key).cloned() else {
+            return;
+        };
+
+        let policy = self.policy();
+        bucket.update(&policy, |tokens| {
+            *tokens = match amount {
+                Some(amount) => tokens.saturating_add(amount).min(policy.capacity),
+                None => policy.capacity,
+            };
+            false
+        });
+        bucket.refilled.notify_waiters();
+    }
+}
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+pub struct Postgres {
+    name: String,
+    policy: RwLock<Policy>,
+    pool: sqlx::PgPool,
+    waiters: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+    pruned_at: std::sync::Mutex<Instant>,
+}
+
+impl Postgres {
+    pub fn new(name: impl Into<String>, policy: Policy, pool: sqlx::PgPool) -> Self {
+        Self {
+            name: name.into(),
+            policy: RwLock::new(policy),
+            pool,
+            waiters: Mutex::new(HashMap::new()),
+            pruned_at: std::sync::Mutex::new(Instant::now()),
+        }
+    }
+
+    // A bucket that has refilled completely behaves like a missing one, so
+    // such rows are deleted every so often instead of keeping one for every
+    // client ever seen.
+    async fn prune(&self) {
+        {
+            let mut pruned_at = self.pruned_at.lock().unwrap();
+            if pruned_at.elapsed() < PRUNE_INTERVAL {
+                return;
+            }
+            *pruned_at = Instant::now();
+        }
+
+        let policy = self.policy();
+        let refills = policy.capacity.div_ceil(policy.refill.max(1));
+        let Some(cutoff) = chrono::Duration::from_std(
+            policy
+                .interval
+                .saturating_mul(u32::try_from(refills).unwrap_or(u32::MAX)),
+        )
+        .ok()
+        .and_then(|full| chrono::Utc::now().checked_sub_signed(full)) else {
+            return;
+        };
+
+        if let Err(e) = sqlx::query!(
+            "DELETE FROM milk_buckets WHERE name LIKE $1 || ':%' AND refilled_at < $2",
+            self.name,
+            cutoff
+        )
+        .execute(&self.pool)
+        .await
+        {
+            tracing::error!("failed to prune milk buckets: {e}");
+        }
+    }
+
+    // Locks nobody holds or waits on any more are dropped on the way.
+    async fn waiter(&self, key: &str) -> Arc<Mutex<()>> {
+        let mut waiters = self.waiters.lock().await;
+        waiters.retain(|_, w| Arc::strong_count(w) > 1);
+
+        waiters.entry(key.to_string()).or_default().clone()
+    }
+
+    fn refilled(
+        &self,
+        tokens: usize,
+        refilled_at: chrono::DateTime<chrono::Utc>,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> (usize, chrono::DateTime<chrono::Utc>) {
+        let elapsed = (now - refilled_at).to_std().unwrap_or_default();
+        let (tokens, refilled) = refilled(&self.policy(), tokens, elapsed);
+
+        (
+            tokens,
+            refilled_at + chrono::Duration::from_std(refilled).unwrap(),
+        )
+    }
+
+    fn bucket_status(
+        &self,
+        acquired: bool,
+        tokens: usize,
+        refilled_at: chrono::DateTime<chrono::Utc>,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Status {
+        let policy = self.policy();
+        let next_refill = if tokens < policy.capacity {
+            policy
+                .interval
+                .saturating_sub((now - refilled_at).to_std().unwrap_or_default())
+        } else {
+            Duration::ZERO
+        };
+
+        Status {
+            acquired,
+            limit: policy.capacity,
+            remaining: tokens,
+            next_refill,
+            policy,
+        }
+    }
+
+    // Settings may be changed through any instance, so they are reloaded
+    // whenever a bucket is used rather than trusted from `set_policy`.
+    async fn reload(&self) {
+        let Some(settings) = sqlx::query!(
+            "SELECT capacity, refill, interval_ms FROM milk_settings WHERE name = $1",
+            self.name
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .unwrap() else {
+            return;
+        };
+
+        let mut policy = self.policy.write().unwrap();
+        *policy = Policy {
+            capacity: settings.capacity as usize,
+            refill: settings.refill as usize,
+            interval: Duration::from_millis(settings.interval_ms as u64),
+            ..*policy
+        };
+    }
+
+    // A missing row cannot be locked, so a full bucket is inserted first and
+    // concurrent first uses all wait on that row instead of each starting full.
+    async fn update<F>(&self, key: &str, f: F) -> Status
+    where
+        F: FnOnce(usize) -> (usize, bool) + Send,
+    {
+        self.reload().await;
+        self.prune().await;
+
+        let name = format!("{}:{key}", self.name);
+        let mut tx = self.pool.begin().await.unwrap();
+        let now = chrono::Utc::now();
+
+        sqlx::query!(
+            "INSERT INTO milk_buckets(name, tokens, refilled_at) VALUES($1, $2, $3) ON CONFLICT (name) DO NOTHING",
+            name,
+            self.policy().capacity as i64,
+            now
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+
+        let bucket = sqlx::query!(
+            "SELECT tokens, refilled_at FROM milk_buckets WHERE name = $1 FOR UPDATE",
+            name
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .unwrap();
+
+        let (tokens, refilled_at) = self.refilled(bucket.tokens as usize, bucket.refilled_at, now);
+        let (tokens, acquired) = f(tokens);
+
+        sqlx::query!(
+            "UPDATE milk_buckets SET tokens = $2, refilled_at = $3 WHERE name = $1",
+            name,
+            tokens as i64,
+            refilled_at
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+
+        tx.commit().await.unwrap();
+
+        self.bucket_status(acquired, tokens, refilled_at, now)
+    }
+}
+
+#[async_trait::async_trait]
+impl Backend for Postgres {
+    fn policy(&self) -> Policy {
+        *self.policy.read().unwrap()
+    }
+
+    async fn set_policy(&self, policy: Policy) {
+        *self.policy.write().unwrap() = policy;
+
+        sqlx::query!(
+            "UPDATE milk_buckets SET tokens = LEAST(tokens, $2) WHERE name LIKE $1 || ':%'",
+            self.name,
+            policy.capacity as i64
+        )
+        .execute(&self.pool)
+        .await
+        .unwrap();
+    }
+
+    async fn try_acquire(&self, key: &str, permits: usize) -> Status {
+        self.update(key, |tokens| {
+            if tokens >= permits {
+                (tokens - permits, true)
+            } else {
+                (tokens, false)
+            }
+        })
+        .await
+    }
+
+    // Waiters queue on a fair mutex per key so they are served in arrival
+    // order, at least within this instance.
+    async fn acquire(&self, key: &str, permits: usize, timeout: Duration) -> Status {
+        let deadline = tokio::time::Instant::now() + timeout;
+        let waiter = self.waiter(key).await;
+
+        let Ok(_waiter) = tokio::time::timeout_at(deadline, waiter.lock()).await else {
+            return self.status(key).await;
+        };
+
+        loop {
+            let status = self.try_acquire(key, permits).await;
+            if status.acquired {
+                return status;
+            }
+
+            let retry_at = tokio::time::Instant::now() + status.retry_after(permits);
+            if retry_at > deadline {
+                return status;
+            }
+
+            tokio::time::sleep_until(retry_at).await;
+        }
+    }
+
+    async fn status(&self, key: &str) -> Status {
+        self.reload().await;
+        let now = chrono::Utc::now();
+
+        let (tokens, refilled_at) = match sqlx::query!(
+            "SELECT tokens, refilled_at FROM milk_buckets WHERE name = $1",
+            format!("{}:{key}", self.name)
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .unwrap()
+        {
+            Some(bucket) => self.refilled(bucket.tokens as usize, bucket.refilled_at, now),
+            None => (self.policy().capacity, now),
+        };
+
+        self.bucket_status(false, tokens, refilled_at, now)
+    }
+
+    async fn refill(&self, key: &str, amount: Option<usize>) {
+        self.update(key, |tokens| {
+            let capacity = self.policy().capacity;
+            let tokens = match amount {
+                Some(amount) => tokens.saturating_add(amount).min(capacity),
+                None => capacity,
+            };
+
+            (tokens, true)
+        })
+        .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory() -> Arc<Memory> {
+        let policy = Policy {
+            capacity: 2,
+            refill: 1,
+            interval: Duration::from_secs(60 * 60),
+            cost: 1,
+        };
+
+        Arc::new(Memory::new(policy, 10, Duration::from_secs(60)))
+    }
+
+    async fn remaining(memory: &Memory, key: &str) -> usize {
+        memory.status(key).await.remaining
+    }
+
+    async fn drain(memory: &Memory) {
+        assert!(memory.try_acquire("elf", 2).await.acquired);
+        assert!(!memory.try_acquire("elf", 1).await.acquired);
+    }
+
+    fn wait(memory: &Arc<Memory>) -> tokio::task::JoinHandle<bool> {
+        let memory = memory.clone();
+
+        tokio::spawn(async move {
+            memory
+                .acquire("elf", 1, Duration::from_millis(500))
+                .await
+                .acquired
+        })
+    }
+
+    #[test]
+    fn refills_whole_intervals() {
+        let policy = Policy {
+            capacity: 10,
+            refill: 2,
+            interval: Duration::from_secs(1),
+            cost: 1,
+        };
+
+        assert_eq!(
+            refilled(&policy, 1, Duration::from_millis(2500)),
+            (5, Duration::from_secs(2))
+        );
+        assert_eq!(
+            refilled(&policy, 8, Duration::from_millis(2500)),
+            (10, Duration::from_millis(2500))
+        );
+    }
+
+    #[test]
+    fn saturates_after_long_idle_times() {
+        let policy = Policy {
+            capacity: usize::MAX,
+            refill: usize::MAX,
+            interval: Duration::from_nanos(1),
+            cost: 1,
+        };
+
+        assert_eq!(
+            refilled(&policy, 1, Duration::MAX),
+            (usize::MAX, Duration::MAX)
+        );
+        assert_eq!(
+            refilled(&policy, 1, Duration::from_millis(30 * 24 * 60 * 60 * 1000)).0,
+            usize::MAX
+        );
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs DATABASE_URL"]
+    async fn serializes_first_use(pool: sqlx::PgPool) {
+        let postgres = Arc::new(Postgres::new("milk", memory().policy(), pool));
+
+        let acquires = (0..4).map(|_| {
+            let postgres = postgres.clone();
+            tokio::spawn(async move { postgres.try_acquire("elf", 2).await.acquired })
+        });
+
+        let mut acquired = 0;
+        for acquire in acquires.collect::<Vec<_>>() {
+            acquired += acquire.await.unwrap() as usize;
+        }
+
+        assert_eq!(acquired, 1);
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs DATABASE_URL"]
+    async fn prunes_refilled_buckets(pool: sqlx::PgPool) {
+        let postgres = Postgres::new("milk", memory().policy(), pool.clone());
+        let stale = chrono::Utc::now() - chrono::Duration::hours(3);
+
+        for (name, refilled_at) in [
+            ("milk:dasher", stale),
+            ("milk:dancer", chrono::Utc::now()),
+            ("cookies:dasher", stale),
+        ] {
+            sqlx::query("INSERT INTO milk_buckets(name, tokens, refilled_at) VALUES($1, 0, $2)")
+                .bind(name)
+                .bind(refilled_at)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+
+        *postgres.pruned_at.lock().unwrap() -= PRUNE_INTERVAL;
+        postgres.try_acquire("prancer", 1).await;
+
+        let names: Vec<String> = sqlx::query_scalar("SELECT name FROM milk_buckets ORDER BY name")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(names, ["cookies:dasher", "milk:dancer", "milk:prancer"]);
+    }
+
+    #[actix_web::test]
+    async fn refill_serves_waiters_first() {
+        let memory = memory();
+        drain(&memory).await;
+
+        let waiter = wait(&memory);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        memory.refill("elf", Some(1)).await;
+
+        assert!(waiter.await.unwrap());
+        assert!(!memory.try_acquire("elf", 1).await.acquired);
+    }
+
+    #[actix_web::test]
+    async fn full_refill_serves_waiters_first() {
+        let memory = memory();
+        drain(&memory).await;
+
+        let waiter = wait(&memory);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        memory.refill("elf", None).await;
+
+        assert!(waiter.await.unwrap());
+        assert_eq!(memory.status("elf").await.remaining, 1);
+    }
+
+    #[actix_web::test]
+    async fn set_policy_keeps_waiters_queued() {
+        let memory = memory();
+        drain(&memory).await;
+
+        let waiter = wait(&memory);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        memory
+            .set_policy(Policy {
+                interval: Duration::from_millis(100),
+                ..memory.policy()
+            })
+            .await;
+
+        assert!(waiter.await.unwrap());
+    }
+
+    #[actix_web::test]
+    async fn reports_refills_after_idle_time() {
+        let memory = Memory::new(
+            Policy {
+                interval: Duration::from_millis(50),
+                ..memory().policy()
+            },
+            10,
+            Duration::from_secs(60),
+        );
+        drain(&memory).await;
+
+        tokio::time::sleep(Duration::from_millis(75)).await;
+        let status = memory.status("elf").await;
+        assert_eq!(status.remaining, 1);
+        assert!(status.next_refill <= Duration::from_millis(25));
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let status = memory.status("elf").await;
+        assert_eq!(status.remaining, 2);
+        assert_eq!(status.reset(), Duration::ZERO);
+    }
+
+    fn fast(memory: &Memory) -> Policy {
+        Policy {
+            interval: Duration::from_millis(50),
+            capacity: 4,
+            ..memory.policy()
+        }
+    }
+
+    #[actix_web::test]
+    async fn refill_adds_to_tokens_accrued_while_idle() {
+        let memory = memory();
+        memory.set_policy(fast(&memory)).await;
+        assert!(memory.try_acquire("elf", 4).await.acquired);
+
+        tokio::time::sleep(Duration::from_millis(75)).await;
+        memory.refill("elf", Some(1)).await;
+
+        assert_eq!(remaining(&memory, "elf").await, 2);
+    }
+
+    #[actix_web::test]
+    async fn set_policy_keeps_accrued_tokens() {
+        let memory = memory();
+        memory.set_policy(fast(&memory)).await;
+        assert!(memory.try_acquire("elf", 4).await.acquired);
+
+        tokio::time::sleep(Duration::from_millis(125)).await;
+        memory
+            .set_policy(Policy {
+                interval: Duration::from_secs(60 * 60),
+                ..fast(&memory)
+            })
+            .await;
+
+        assert_eq!(remaining(&memory, "elf").await, 2);
+    }
+
+    #[actix_web::test]
+    async fn refill_saturates() {
+        let memory = memory();
+        drain(&memory).await;
+        memory.refill("elf", Some(usize::MAX)).await;
+
+        assert_eq!(remaining(&memory, "elf").await, 2);
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs DATABASE_URL"]
+    async fn postgres_refill_saturates(pool: sqlx::PgPool) {
+        let postgres = Postgres::new("milk", memory().policy(), pool);
+        assert!(postgres.try_acquire("elf", 2).await.acquired);
+        postgres.refill("elf", Some(usize::MAX)).await;
+
+        assert_eq!(postgres.status("elf").await.remaining, 2);
+    }
+
+    #[actix_web::test]
+    async fn evicts_least_recently_used() {
+        let memory = Memory::new(memory().policy(), 2, Duration::from_secs(60));
+
+        memory.try_acquire("dasher", 1).await;
+        memory.try_acquire("dancer", 1).await;
+        memory.try_acquire("dasher", 1).await;
+        memory.try_acquire("prancer", 1).await;
+
+        assert_eq!(remaining(&memory, "dasher").await, 0);
+        assert_eq!(remaining(&memory, "dancer").await, 2);
+        assert_eq!(remaining(&memory, "prancer").await, 1);
+    }
+
+    #[actix_web::test]
+    async fn evicts_idle_buckets() {
+        let memory = Memory::new(memory().policy(), 10, Duration::from_millis(50));
+
+        memory.try_acquire("dasher", 1).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        memory.try_acquire("dancer", 1).await;
+
+        assert_eq!(memory.buckets.lock().await.len(), 1);
+        assert_eq!(remaining(&memory, "dasher").await, 2);
+    }
+}
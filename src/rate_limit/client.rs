@@ -0,0 +1,217 @@
+use std::{collections::HashSet, net::IpAddr};
+
+use actix_web::HttpRequest;
+use sha2::Digest;
+
+enum KeyBy {
+    Global,
+    Ip,
+    // Only configured keys get a bucket of their own, otherwise rotating the
+    // header would hand out fresh buckets; unknown keys share the IP's.
+    ApiKey {
+        header: String,
+        keys: HashSet<String>,
+    },
+    // Anyone can wrap a gift for any subject, so likewise only configured
+    // subjects are trusted.
+    Subject {
+        subjects: HashSet<String>,
+    },
+}
+
+// Keys end up in bucket names and the ledger, so they are identified by a
+// digest instead of the secret itself.
+fn key_id(key: &str) -> String {
+    format!("{:x}", sha2::Sha256::digest(key))[..16].to_string()
+}
+
+fn list(secrets: &shuttle_runtime::SecretStore, name: &str) -> HashSet<String> {
+    secrets
+        .get(name)
+        .map(|l| {
+            l.split(',')
+                .map(|i| i.trim().to_string())
+                .filter(|i| !i.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub struct Keys {
+    by: KeyBy,
+    trusted_proxies: Vec<IpAddr>,
+}
+
+impl Keys {
+    pub fn from_secrets(secrets: &shuttle_runtime::SecretStore) -> Self {
+        let by = match secrets.get("RATE_LIMIT_KEY").as_deref() {
+            Some("ip") => KeyBy::Ip,
+            Some("api_key") => {
+                let keys = list(secrets, "RATE_LIMIT_API_KEYS");
+                if keys.is_empty() {
+                    tracing::warn!("no RATE_LIMIT_API_KEYS configured, limiting by ip");
+                }
+
+                KeyBy::ApiKey {
+                    header: secrets
+                        .get("RATE_LIMIT_API_KEY_HEADER")
+                        .unwrap_or_else(|| "X-Api-Key".to_string()),
+                    keys,
+                }
+            }
+            Some("subject") => {
+                let subjects = list(secrets, "RATE_LIMIT_SUBJECTS");
+                if subjects.is_empty() {
+                    tracing::warn!("no RATE_LIMIT_SUBJECTS configured, limiting by ip");
+                }
+
+                KeyBy::Subject { subjects }
+            }
+            _ => KeyBy::Global,
+        };
+
+        let trusted_proxies = secrets
+            .get("RATE_LIMIT_TRUSTED_PROXIES")
+            .map(|p| p.split(',').filter_map(|p| p.trim().parse().ok()).collect())
+            .unwrap_or_default();
+
+        Self {
+            by,
+            trusted_proxies,
+        }
+    }
+
+    pub fn key(&self, req: &HttpRequest) -> String {
+        let key = match &self.by {
+            KeyBy::Global => return "global".to_string(),
+            KeyBy::Ip => None,
+            KeyBy::ApiKey { header, keys } => req
+                .headers()
+                .get(header)
+                .and_then(|k| k.to_str().ok())
+                .filter(|k| keys.contains(*k))
+                .map(|k| format!("api_key:{}", key_id(k))),
+            KeyBy::Subject { subjects } => crate::day16::gift_subject(req)
+                .filter(|s| subjects.contains(s))
+                .map(|s| format!("sub:{s}")),
+        };
+
+        key.unwrap_or_else(|| match self.client_ip(req) {
+            Some(ip) => format!("ip:{ip}"),
+            None => "ip:unknown".to_string(),
+        })
+    }
+
+    fn client_ip(&self, req: &HttpRequest) -> Option<IpAddr> {
+        let peer = req.peer_addr()?.ip();
+
+        if !self.trusted_proxies.contains(&peer) {
+            return Some(peer);
+        }
+
+        let forwarded_for = req
+            .headers()
+            .get_all("X-Forwarded-For")
+            .filter_map(|f| f.to_str().ok())
+            .flat_map(|f| f.split(','))
+            .filter_map(|ip| ip.trim().parse::<IpAddr>().ok())
+            .collect::<Vec<_>>();
+
+        Some(
+            forwarded_for
+                .iter()
+                .rev()
+                .find(|ip| !self.trusted_proxies.contains(ip))
+                .or(forwarded_for.first())
+                .copied()
+                .unwrap_or(peer),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use actix_web::{test::TestRequest, web};
+    use shuttle_runtime::SecretStore;
+
+    use super::*;
+
+    fn keys(secrets: &[(&str, &str)]) -> Keys {
+        let secrets = secrets
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string().into()))
+            .collect::<BTreeMap<_, _>>();
+
+        Keys::from_secrets(&SecretStore::new(secrets))
+    }
+
+    fn request(api_key: Option<&str>) -> HttpRequest {
+        let req = TestRequest::default().peer_addr("10.0.0.1:4000".parse().unwrap());
+
+        match api_key {
+            Some(api_key) => req.insert_header(("X-Api-Key", api_key)),
+            None => req,
+        }
+        .to_http_request()
+    }
+
+    #[test]
+    fn keys_configured_api_keys() {
+        let keys = keys(&[
+            ("RATE_LIMIT_KEY", "api_key"),
+            ("RATE_LIMIT_API_KEYS", "elf-1, elf-2"),
+        ]);
+
+        let elf_1 = keys.key(&request(Some("elf-1")));
+        let elf_2 = keys.key(&request(Some("elf-2")));
+
+        assert_eq!(elf_1, format!("api_key:{}", key_id("elf-1")));
+        assert_ne!(elf_1, elf_2);
+        assert!(!elf_1.contains("elf"));
+    }
+
+    #[test]
+    fn unknown_api_keys_fall_back_to_ip() {
+        let keys = keys(&[
+            ("RATE_LIMIT_KEY", "api_key"),
+            ("RATE_LIMIT_API_KEYS", "elf-1"),
+        ]);
+
+        assert_eq!(keys.key(&request(Some("grinch"))), "ip:10.0.0.1");
+        assert_eq!(keys.key(&request(Some("elf-1,grinch"))), "ip:10.0.0.1");
+        assert_eq!(keys.key(&request(None)), "ip:10.0.0.1");
+    }
+
+    #[test]
+    fn keys_configured_subjects() {
+        let keys = keys(&[
+            ("RATE_LIMIT_KEY", "subject"),
+            ("RATE_LIMIT_SUBJECTS", "elf-1"),
+        ]);
+        let config = web::Data::new(crate::day16::tests::config(&[]));
+
+        let with_gift = |sub: &str| {
+            TestRequest::default()
+                .peer_addr("10.0.0.1:4000".parse().unwrap())
+                .app_data(config.clone())
+                .insert_header((
+                    "Authorization",
+                    format!("Bearer {}", crate::day16::tests::gift(&config, sub)),
+                ))
+                .to_http_request()
+        };
+
+        assert_eq!(keys.key(&with_gift("elf-1")), "sub:elf-1");
+        assert_eq!(keys.key(&with_gift("grinch")), "ip:10.0.0.1");
+        assert_eq!(keys.key(&request(None)), "ip:10.0.0.1");
+    }
+
+    #[test]
+    fn api_keys_need_configuring() {
+        let keys = keys(&[("RATE_LIMIT_KEY", "api_key")]);
+
+        assert_eq!(keys.key(&request(Some("elf-1"))), "ip:10.0.0.1");
+    }
+}